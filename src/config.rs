@@ -1,6 +1,15 @@
+use std::path::PathBuf;
+
 use clap::Clap;
 
-use crate::mcu::{Frequency, Port, Pin, StopBits};
+use crate::{
+    mcu::{Frequency, Port, Pin, StopBits, DataBits, Parity, Timer, SyncChar, BaudRates},
+    device::Device,
+    loopback,
+    peripheral::OutputOptions,
+    report::ReportFormat,
+    uart::{UartConfig, UartOptions},
+};
 
 #[derive(Clap)]
 #[clap(
@@ -11,7 +20,9 @@ name = env!("CARGO_PKG_NAME")
 )]
 pub struct AppConfig {
     #[clap(long, short, about = "MCU frequency")]
-    pub freq: Frequency,
+    pub freq: Option<Frequency>,
+    #[clap(long, short, about = "Target device, optionally with package (e.g. PFS154 or PFS154-S08)")]
+    pub device: Option<Device>,
     #[clap(subcommand)]
     pub subcommand: AppSubcommand,
 }
@@ -20,26 +31,206 @@ pub struct AppConfig {
 pub enum AppSubcommand {
     #[clap(about = "Generate software uart implementation")]
     Uart(UartSubcommand),
+    #[clap(about = "Run generated UART TX/RX in simulated loopback for a grid of settings")]
+    Loopback(LoopbackSubcommand),
+    #[clap(about = "Generate all peripherals described in the project file")]
+    Build(BuildSubcommand),
+    #[clap(about = "Print the built-in template, a starting point for the `--template` option")]
+    DumpTemplate(DumpTemplateSubcommand),
 }
 
+/// UART options of the command line; Converted to `UartOptions`
 #[derive(Clap)]
 pub struct UartSubcommand {
-    #[clap(long, about = "Sets generated UART baud rate")]
-    pub baud: u32,
-    #[clap(long, about = "Port to use for UART TX pin")]
-    pub tx_port: Port,
+    #[clap(subcommand)]
+    pub command: Option<UartCommand>,
+    #[clap(long, about = "Sets generated UART baud rate; A comma-separated list adds `uart<N>_set_baud(index)`, the first one is initial")]
+    pub baud: Option<BaudRates>,
+    #[clap(long, about = "Port to use for UART TX pin; TX is not generated when omitted")]
+    pub tx_port: Option<Port>,
     #[clap(long, about = "Pin to use for UART TX")]
-    pub tx_pin: Pin,
+    pub tx_pin: Option<Pin>,
     #[clap(long, about = "Invert UART TX logic level")]
     pub invert_tx: bool,
-    #[clap(long, about = "Port to use for UART RX pin")]
-    pub rx_port: Port,
+    #[clap(long, about = "Port to use for UART RX pin; RX is not generated when omitted")]
+    pub rx_port: Option<Port>,
     #[clap(long, about = "Pin to use for UART RX")]
-    pub rx_pin: Pin,
+    pub rx_pin: Option<Pin>,
     #[clap(long, about = "Invert UART RX logic level")]
     pub invert_rx: bool,
+    #[clap(long, about = "Use a single open-drain line for both TX and RX")]
+    pub half_duplex: bool,
+    #[clap(long, about = "Read back the line while sending to detect collisions (half-duplex only)")]
+    pub echo_check: bool,
     #[clap(long, about = "Customize generated UART TX function name", default_value = "0")]
     pub uart_num: u8,
     #[clap(long, about = "Set stop bits count; Available values: 1, 2, 1.5", default_value = "1")]
     pub stop_bits: StopBits,
-}
\ No newline at end of file
+    #[clap(long, about = "Set data bits count; Available values: 5..9", default_value = "8")]
+    pub data_bits: DataBits,
+    #[clap(long, about = "Set parity; Available values: none, even, odd, mark, space", default_value = "none")]
+    pub parity: Parity,
+    #[clap(long, about = "Extra idle line time after each sent frame, bits", default_value = "0")]
+    pub idle_bits: u32,
+    #[clap(long, about = "Give each bit its own clocks count, so the rounding error doesn't add up over the frame")]
+    pub fractional_timing: bool,
+    #[clap(long, about = "Max baud rate deviation due to clock rounding, percents [default: 1]")]
+    pub max_deviation: Option<f64>,
+    #[clap(long, about = "MCU clock tolerance counted in the timing error budget, percents [default: 1]")]
+    pub clock_tolerance: Option<f64>,
+    #[clap(long, about = "Receive from the start bit interrupt into a ring buffer; RX should be on PA0 or PB0")]
+    pub rx_interrupt: bool,
+    #[clap(long, about = "Ring buffer size of the interrupt-driven RX, power of two [default: 8]")]
+    pub rx_buffer_size: Option<u8>,
+    #[clap(long, about = "Clock the bits from a hardware timer interrupt; Available values: t16, tm2, tm3")]
+    pub timer: Option<Timer>,
+    #[clap(long, about = "Ring buffer size of the timer-driven TX, power of two; Adds non-blocking `uart<N>_write`")]
+    pub tx_buffer_size: Option<u8>,
+    #[clap(long, about = "Add `uart<N>_receive_timeout`, which gives up waiting for the start bit")]
+    pub rx_timeout: bool,
+    #[clap(long, about = "Add `uart<N>_autobaud`, which measures the bit period on this sync character; Available values: 0x55, 0x7f")]
+    pub autobaud: Option<SyncChar>,
+    #[clap(long, about = "Output file; Directory for the split output. Code is printed when omitted")]
+    pub output: Option<PathBuf>,
+    #[clap(long, about = "Split the code into `uart<N>.h` with declarations and `uart<N>.c` implementation")]
+    pub split: bool,
+    #[clap(long, about = "Write timing report of the generated code; Available values: json")]
+    pub report: Option<ReportFormat>,
+    #[clap(long, about = "File for the report; Report is printed after the generated code when omitted")]
+    pub report_file: Option<PathBuf>,
+    #[clap(long, about = "Custom template of the single-file output, see `dump-template`")]
+    pub template: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub enum UartCommand {
+    #[clap(about = "List frequency and baud rate combinations the UART could be generated for")]
+    Suggest(SuggestSubcommand),
+}
+
+#[derive(Clap)]
+pub struct SuggestSubcommand {
+    #[clap(long, about = "List standard baud rates for this frequency; Global --freq is used when omitted")]
+    pub freq: Option<Frequency>,
+    #[clap(long, about = "List system clock options of the device for this baud rate", conflicts_with = "freq")]
+    pub baud: Option<u32>,
+    #[clap(long, about = "Give each bit its own clocks count, so the rounding error doesn't add up over the frame")]
+    pub fractional_timing: bool,
+    #[clap(long, about = "Max baud rate deviation due to clock rounding, percents [default: 1]")]
+    pub max_deviation: Option<f64>,
+    #[clap(long, about = "MCU clock tolerance counted in the timing error budget, percents [default: 1]")]
+    pub clock_tolerance: Option<f64>,
+    #[clap(long, about = "Also list rejected combinations with the reason")]
+    pub all: bool,
+}
+
+#[derive(Clap)]
+pub struct LoopbackSubcommand {
+    #[clap(
+        long,
+        about = "MCU frequencies to check",
+        use_delimiter = true,
+        default_value = loopback::DEFAULT_FREQUENCIES
+    )]
+    pub freq: Vec<Frequency>,
+    #[clap(
+        long,
+        about = "Baud rates to check",
+        use_delimiter = true,
+        default_value = loopback::DEFAULT_BAUD_RATES
+    )]
+    pub baud: Vec<u32>,
+    #[clap(long, about = "Port of the TX pin; A free pin of the --device is chosen when omitted", requires = "tx-pin")]
+    pub tx_port: Option<Port>,
+    #[clap(long, about = "Pin of the TX", requires = "tx-port")]
+    pub tx_pin: Option<Pin>,
+    #[clap(long, about = "Port of the RX pin; A free pin of the --device is chosen when omitted", requires = "rx-pin")]
+    pub rx_port: Option<Port>,
+    #[clap(long, about = "Pin of the RX", requires = "rx-port")]
+    pub rx_pin: Option<Pin>,
+    #[clap(long, about = "TX baud rate deviation from RX baud rate, percents", default_value = "0")]
+    pub baud_mismatch: f64,
+    #[clap(long, about = "Max random displacement of each TX edge, clocks", default_value = "0")]
+    pub jitter: u32,
+    #[clap(long, about = "Set stop bits count; Available values: 1, 2, 1.5", default_value = "1")]
+    pub stop_bits: StopBits,
+    #[clap(long, about = "Set data bits count; Available values: 5..9", default_value = "8")]
+    pub data_bits: DataBits,
+    #[clap(long, about = "Set parity; Available values: none, even, odd, mark, space", default_value = "none")]
+    pub parity: Parity,
+    #[clap(long, about = "Extra idle line time after each sent frame, bits", default_value = "0")]
+    pub idle_bits: u32,
+    #[clap(long, about = "Use a single open-drain line for both TX and RX")]
+    pub half_duplex: bool,
+    #[clap(long, about = "Read back the line while sending to detect collisions (half-duplex only)")]
+    pub echo_check: bool,
+    #[clap(long, about = "Give each bit its own clocks count, so the rounding error doesn't add up over the frame")]
+    pub fractional_timing: bool,
+    #[clap(long, about = "Receive in the start bit interrupt and read the words from the buffer")]
+    pub rx_interrupt: bool,
+    #[clap(long, about = "Clock the bits from a hardware timer interrupt; Available values: t16, tm2, tm3")]
+    pub timer: Option<Timer>,
+    #[clap(long, about = "Send through the TX ring buffer of this size (requires --timer)")]
+    pub tx_buffer_size: Option<u8>,
+    #[clap(long, about = "Receive with `uart<N>_receive_timeout` instead of waiting for the start bit")]
+    pub rx_timeout: bool,
+    #[clap(long, about = "Send the sync character and receive with the period measured by `uart<N>_autobaud`")]
+    pub autobaud: Option<SyncChar>,
+    #[clap(long, about = "Generate all --baud values as the `uart<N>_set_baud` table and check each entry")]
+    pub baud_table: bool,
+}
+
+#[derive(Clap)]
+pub struct BuildSubcommand {
+    #[clap(about = "Project file (.toml, .yaml or .yml)")]
+    pub project: PathBuf,
+}
+
+#[derive(Clap)]
+pub struct DumpTemplateSubcommand {
+    #[clap(about = "Peripheral to print the template of", default_value = "uart")]
+    pub peripheral: String,
+}
+
+impl From<&UartSubcommand> for UartOptions {
+    fn from(uart: &UartSubcommand) -> Self {
+        let config = UartConfig {
+            frequency: None,
+            device: None,
+            baud: None,
+            tx_port: uart.tx_port,
+            tx_pin: uart.tx_pin,
+            invert_tx: uart.invert_tx,
+            rx_port: uart.rx_port,
+            rx_pin: uart.rx_pin,
+            invert_rx: uart.invert_rx,
+            half_duplex: uart.half_duplex,
+            echo_check: uart.echo_check,
+            uart_num: uart.uart_num,
+            stop_bits: uart.stop_bits,
+            data_bits: uart.data_bits,
+            parity: uart.parity,
+            idle_bits: uart.idle_bits,
+            fractional_timing: uart.fractional_timing,
+            max_deviation: uart.max_deviation,
+            clock_tolerance: uart.clock_tolerance,
+            rx_interrupt: uart.rx_interrupt,
+            rx_buffer_size: uart.rx_buffer_size,
+            timer: uart.timer,
+            tx_buffer_size: uart.tx_buffer_size,
+            rx_timeout: uart.rx_timeout,
+            autobaud: uart.autobaud,
+            baud_table: Vec::new(),
+        };
+        let output = OutputOptions {
+            output: uart.output.clone(),
+            split: uart.split,
+            report: uart.report,
+            report_file: uart.report_file.clone(),
+        };
+        let mut options = UartOptions::new(config, output);
+        options.baud = uart.baud.clone();
+        options.template = uart.template.clone();
+        options
+    }
+}
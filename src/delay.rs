@@ -0,0 +1,209 @@
+//! Busy-wait code synthesizer
+//!
+//! Produces instruction sequences which take exactly the requested count of clocks. Generated
+//! code uses relative jumps only (no labels), clobbers `a` and flags, and may use RAM counter
+//! (`counter` and `counter+1`) for long delays.
+
+use serde::Serialize;
+
+/// Max iterations of the 8-bit loop counter (encoded as 0)
+const SINGLE_LOOP_MAX_ITERATIONS: u32 = 256;
+const WIDE_LOOP_MAX_BODY_COUNT: u32 = 256 * 256;
+/// Delays shorter than this are cheaper (in words) as a nop chain than as a loop
+const NOP_CHAIN_MAX_CLOCKS: u32 = 7;
+const SINGLE_LOOP_MAX_CLOCKS: u32 = single_loop_clocks(SINGLE_LOOP_MAX_ITERATIONS) + 2;
+const NESTED_LOOP_MAX_CLOCKS: u32 =
+    nested_loop_clocks(SINGLE_LOOP_MAX_ITERATIONS, SINGLE_LOOP_MAX_ITERATIONS);
+
+const fn single_loop_clocks(iterations: u32) -> u32 {
+    // mov a (1T) + (iterations - 1) * (dzsn + goto) + dzsn with skip (2T)
+    3 * iterations
+}
+
+const fn nested_loop_clocks(outer: u32, inner: u32) -> u32 {
+    // Counter setup (2T) + outer iterations, the last one is 1T shorter
+    outer * (single_loop_clocks(inner) + 3) + 1
+}
+
+fn wide_loop_clocks(body_count: u32, inner: u32) -> u32 {
+    // Counter setup (4T) + body + low counter decrement (3T, 2T on zero) + high counter decrement
+    // on low counter zero (3T, 2T on exit)
+    let high = wide_loop_high_count(body_count);
+    single_loop_clocks(inner) * body_count + 3 * body_count + 2 * high + 3
+}
+
+fn wide_loop_high_count(body_count: u32) -> u32 {
+    body_count.div_ceil(256)
+}
+
+/// Busy-wait loop of the synthesized delay
+#[derive(Clone, Debug, Serialize)]
+pub struct DelayLoop {
+    /// Iteration counts from the outermost to the innermost loop
+    pub counts: Vec<u32>,
+    pub clocks: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Delay {
+    instructions: Vec<String>,
+    counter_bytes: u8,
+    clocks: u32,
+    loops: Vec<DelayLoop>,
+    tail_nops: u32,
+}
+
+impl Delay {
+    /// Synthesizes delay of exactly `clocks` clocks
+    pub fn new(clocks: u32, counter: &str) -> Self {
+        let mut delay = Self { clocks, ..Self::default() };
+        delay.synthesize(clocks, counter);
+        delay
+    }
+
+    /// Delay of `nop`/`goto .+1` only, which doesn't affect `a` and flags
+    pub fn nops(clocks: u32) -> Self {
+        let mut delay = Self { clocks, ..Self::default() };
+        delay.nop_chain(clocks);
+        delay
+    }
+
+    pub fn instructions(&self) -> &[String] {
+        &self.instructions
+    }
+
+    pub fn into_instructions(self) -> Vec<String> {
+        self.instructions
+    }
+
+    /// Count of RAM counter bytes used by the delay code (0, 1 or 2)
+    pub fn counter_bytes(&self) -> u8 {
+        self.counter_bytes
+    }
+
+    /// Count of generated instruction words
+    pub fn words(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Count of clocks taken by the delay (without the code pushed via `push_code`)
+    pub fn clocks(&self) -> u32 {
+        self.clocks
+    }
+
+    pub fn loops(&self) -> &[DelayLoop] {
+        &self.loops
+    }
+
+    /// Count of `nop`/`goto .+1` instructions which pad the delay to the exact clocks count
+    pub fn tail_nops(&self) -> u32 {
+        self.tail_nops
+    }
+
+    /// Appends another delay, resulting delay takes the sum of both delays
+    pub fn append(&mut self, other: Delay) {
+        self.counter_bytes = self.counter_bytes.max(other.counter_bytes);
+        self.instructions.extend(other.instructions);
+        self.clocks += other.clocks;
+        self.loops.extend(other.loops);
+        self.tail_nops += other.tail_nops;
+    }
+
+    /// Appends fixed-time code (e.g. pin sampling) in the middle of the delay; Its clocks are
+    /// not accounted by the delay itself
+    pub fn push_code(&mut self, instructions: impl IntoIterator<Item = String>) {
+        self.instructions.extend(instructions);
+    }
+
+    fn push(&mut self, instruction: String) {
+        self.instructions.push(instruction);
+    }
+
+    fn synthesize(&mut self, clocks: u32, counter: &str) {
+        if clocks <= NOP_CHAIN_MAX_CLOCKS {
+            self.nop_chain(clocks);
+        } else if clocks <= SINGLE_LOOP_MAX_CLOCKS {
+            let iterations = (clocks / 3).min(SINGLE_LOOP_MAX_ITERATIONS);
+            self.single_loop(iterations);
+            self.nop_chain(clocks - single_loop_clocks(iterations));
+        } else if clocks <= NESTED_LOOP_MAX_CLOCKS {
+            let max_outer_iteration = single_loop_clocks(SINGLE_LOOP_MAX_ITERATIONS) + 3;
+            let outer = (clocks - 1).div_ceil(max_outer_iteration);
+            let inner = (((clocks - 1) / outer - 3) / 3).min(SINGLE_LOOP_MAX_ITERATIONS);
+            self.nested_loop(outer, inner, counter);
+            self.synthesize(clocks - nested_loop_clocks(outer, inner), counter);
+        } else {
+            let inner = SINGLE_LOOP_MAX_ITERATIONS;
+            let mut body_count = ((clocks - 3) / (single_loop_clocks(inner) + 3))
+                .min(WIDE_LOOP_MAX_BODY_COUNT);
+            while wide_loop_clocks(body_count, inner) > clocks {
+                body_count -= 1;
+            }
+            self.wide_loop(body_count, inner, counter);
+            self.synthesize(clocks - wide_loop_clocks(body_count, inner), counter);
+        }
+    }
+
+    fn nop_chain(&mut self, clocks: u32) {
+        self.tail_nops += clocks / 2 + clocks % 2;
+        for _ in 0..clocks / 2 {
+            self.push("goto .+1 ; 2T".to_string());
+        }
+        if !clocks.is_multiple_of(2) {
+            self.push("nop ; 1T".to_string());
+        }
+    }
+
+    fn single_loop(&mut self, iterations: u32) {
+        self.loops.push(DelayLoop { counts: vec![iterations], clocks: single_loop_clocks(iterations) });
+        // 256 iterations are encoded as 0
+        self.push(format!(
+            "mov a, #{} ; 1T, loop takes {}T in total",
+            iterations % 256,
+            single_loop_clocks(iterations)
+        ));
+        self.push("dzsn a ; 1T, 2T on exit".to_string());
+        self.push("goto .-1 ; 2T".to_string());
+    }
+
+    fn nested_loop(&mut self, outer: u32, inner: u32, counter: &str) {
+        self.counter_bytes = self.counter_bytes.max(1);
+        self.loops.push(DelayLoop { counts: vec![outer, inner], clocks: nested_loop_clocks(outer, inner) });
+        self.push(format!(
+            "mov a, #{} ; 1T, nested loop takes {}T in total",
+            outer % 256,
+            nested_loop_clocks(outer, inner)
+        ));
+        self.push(format!("mov {}, a ; 1T", counter));
+        self.push(format!("mov a, #{} ; 1T", inner % 256));
+        self.push("dzsn a ; 1T, 2T on exit".to_string());
+        self.push("goto .-1 ; 2T".to_string());
+        self.push(format!("dzsn {} ; 1T, 2T on exit", counter));
+        self.push("goto .-4 ; 2T".to_string());
+    }
+
+    fn wide_loop(&mut self, body_count: u32, inner: u32, counter: &str) {
+        self.counter_bytes = 2;
+        self.loops.push(DelayLoop {
+            counts: vec![body_count, inner],
+            clocks: wide_loop_clocks(body_count, inner),
+        });
+        let high = wide_loop_high_count(body_count);
+        let low = body_count - 256 * (high - 1);
+        self.push(format!(
+            "mov a, #{} ; 1T, 16-bit counter loop takes {}T in total",
+            low % 256,
+            wide_loop_clocks(body_count, inner)
+        ));
+        self.push(format!("mov {}, a ; 1T", counter));
+        self.push(format!("mov a, #{} ; 1T", high % 256));
+        self.push(format!("mov {}+1, a ; 1T", counter));
+        self.push(format!("mov a, #{} ; 1T", inner % 256));
+        self.push("dzsn a ; 1T, 2T on exit".to_string());
+        self.push("goto .-1 ; 2T".to_string());
+        self.push(format!("dzsn {} ; 1T, 2T on zero", counter));
+        self.push("goto .-4 ; 2T".to_string());
+        self.push(format!("dzsn {}+1 ; 1T, 2T on exit", counter));
+        self.push("goto .-6 ; 2T".to_string());
+    }
+}
@@ -0,0 +1,426 @@
+use std::str::FromStr;
+use std::fmt::{Display, Formatter};
+
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+use crate::mcu::{Port, Pin, Timer, impl_deserialize_from_str};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Port {} is not available on {}", _0, _1)]
+    PortNotAvailable(Port, String),
+    #[error("Pin {}{} is not available on {}", _0, _1.num(), _2)]
+    PinNotAvailable(Port, Pin, String),
+    #[error("Timer {} is not available on {}", _0, _1)]
+    TimerNotAvailable(Timer, String),
+    #[error("Generated code requires {} bytes of RAM, but {} has only {}", _0, _1, _2)]
+    NotEnoughRam(u16, String, u16),
+}
+
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub enum Core {
+    Pdk13,
+    Pdk14,
+    Pdk15,
+}
+
+impl Core {
+    pub fn word_bits(self) -> u8 {
+        match self {
+            Self::Pdk13 => 13,
+            Self::Pdk14 => 14,
+            Self::Pdk15 => 15,
+        }
+    }
+}
+
+/// Pin mask of a single IO port; bit N is set when PxN is present
+#[derive(Clone, Copy, Debug)]
+pub struct PortPins {
+    pub port: char,
+    pub mask: u8,
+}
+
+#[derive(Debug)]
+pub struct Package {
+    pub name: &'static str,
+    pub pins: &'static [PortPins],
+}
+
+/// Pin with a function besides the general purpose IO
+#[derive(Debug)]
+pub struct SpecialPin {
+    pub port: char,
+    pub pin: u8,
+    pub function: &'static str,
+    /// Pin has no push-pull output driver (but still could pull the line low)
+    pub input_only: bool,
+}
+
+/// Internal oscillator with the system clock dividers available for it
+#[derive(Debug)]
+pub struct ClockSource {
+    pub name: &'static str,
+    /// Nominal (calibrated) frequency
+    pub hz: u32,
+    pub dividers: &'static [u32],
+}
+
+impl ClockSource {
+    /// System clock options, e.g. `IHRC/2` and its frequency
+    pub fn options(&self) -> impl Iterator<Item = (String, u32)> + '_ {
+        self.dividers
+            .iter()
+            .map(move |divider| (format!("{}/{}", self.name, divider), self.hz / divider))
+    }
+}
+
+#[derive(Debug)]
+pub struct DeviceInfo {
+    pub name: &'static str,
+    pub core: Core,
+    pub rom_words: u16,
+    pub ram_bytes: u16,
+    /// IO pins available on the die
+    pub pins: &'static [PortPins],
+    pub packages: &'static [Package],
+    pub special_pins: &'static [SpecialPin],
+    pub clock_sources: &'static [ClockSource],
+    pub timers: &'static [Timer],
+}
+
+const fn port(port: char, mask: u8) -> PortPins {
+    PortPins { port, mask }
+}
+
+// PA1 and PA2 are not bonded out on most of the parts
+const PA_COMMON: u8 = 0b1111_1001;
+const PA_SOT23: u8 = 0b0111_1000;
+
+// PA5 is shared with reset and is open-drain only; PA3, PA5 and PA6 are used by the programmer
+const SPECIAL_PINS_COMMON: &[SpecialPin] = &[
+    SpecialPin { port: 'A', pin: 3, function: "programming clock", input_only: false },
+    SpecialPin { port: 'A', pin: 5, function: "reset and programming voltage", input_only: true },
+    SpecialPin { port: 'A', pin: 6, function: "programming data", input_only: false },
+];
+
+// SYSCLK is IHRC (16MHz) or ILRC divided via CLKMD
+const CLOCK_SOURCES_COMMON: &[ClockSource] = &[
+    ClockSource { name: "IHRC", hz: 16_000_000, dividers: &[2, 4, 8, 16, 32, 64] },
+    ClockSource { name: "ILRC", hz: 55_000, dividers: &[1, 4, 16] },
+];
+
+// 16-bit timer and 8-bit PWM timers
+const TIMERS_T16_TM2: &[Timer] = &[Timer::T16, Timer::Tm2];
+const TIMERS_T16_TM2_TM3: &[Timer] = &[Timer::T16, Timer::Tm2, Timer::Tm3];
+
+const DEVICES: &[DeviceInfo] = &[
+    DeviceInfo {
+        name: "PMS150C",
+        core: Core::Pdk13,
+        rom_words: 1024,
+        ram_bytes: 64,
+        pins: &[port('A', PA_COMMON)],
+        packages: &[
+            Package { name: "U06", pins: &[port('A', PA_SOT23)] },
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2,
+    },
+    DeviceInfo {
+        name: "PFS154",
+        core: Core::Pdk14,
+        rom_words: 2048,
+        ram_bytes: 128,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+    DeviceInfo {
+        name: "PFS172",
+        core: Core::Pdk14,
+        rom_words: 2048,
+        ram_bytes: 128,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+    DeviceInfo {
+        name: "PFS173",
+        core: Core::Pdk15,
+        rom_words: 3072,
+        ram_bytes: 256,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF), port('C', 0b1100_0000)],
+        packages: &[
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+            Package {
+                name: "S20",
+                pins: &[port('A', PA_COMMON), port('B', 0xFF), port('C', 0b1100_0000)],
+            },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+    DeviceInfo {
+        name: "PMS152",
+        core: Core::Pdk14,
+        rom_words: 1280,
+        ram_bytes: 80,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+    DeviceInfo {
+        name: "PMS171B",
+        core: Core::Pdk14,
+        rom_words: 1536,
+        ram_bytes: 96,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2,
+    },
+    DeviceInfo {
+        name: "PFC151",
+        core: Core::Pdk14,
+        rom_words: 2048,
+        ram_bytes: 128,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+    DeviceInfo {
+        name: "PFC161",
+        core: Core::Pdk14,
+        rom_words: 2048,
+        ram_bytes: 128,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "U06", pins: &[port('A', PA_SOT23)] },
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2,
+    },
+    DeviceInfo {
+        name: "PFC232",
+        core: Core::Pdk14,
+        rom_words: 2048,
+        ram_bytes: 128,
+        pins: &[port('A', PA_COMMON), port('B', 0xFF)],
+        packages: &[
+            Package { name: "S08", pins: &[port('A', PA_COMMON)] },
+            Package { name: "S14", pins: &[port('A', PA_COMMON), port('B', 0b0011_0110)] },
+            Package { name: "S16", pins: &[port('A', PA_COMMON), port('B', 0xFF)] },
+        ],
+        special_pins: SPECIAL_PINS_COMMON,
+        clock_sources: CLOCK_SOURCES_COMMON,
+        timers: TIMERS_T16_TM2_TM3,
+    },
+];
+
+/// Device selected by user; When package is not specified, all pins of the die are allowed
+#[derive(Clone, Copy, Debug)]
+pub struct Device {
+    info: &'static DeviceInfo,
+    package: Option<&'static Package>,
+}
+
+impl FromStr for Device {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_ascii_uppercase();
+        let (name, package_name) = match normalized.find('-') {
+            Some(split_pos) => (&normalized[..split_pos], Some(&normalized[split_pos + 1..])),
+            None => (normalized.as_str(), None),
+        };
+
+        let info = DEVICES.iter().find(|device| device.name == name).ok_or_else(|| {
+            let known_devices: Vec<_> = DEVICES.iter().map(|device| device.name).collect();
+            format!("Unknown device; Supported devices: {}", known_devices.join(", "))
+        })?;
+
+        let package = match package_name {
+            None => None,
+            Some(package_name) => {
+                let package = info.packages.iter()
+                    .find(|package| package.name == package_name)
+                    .ok_or_else(|| {
+                        let known_packages: Vec<_> = info.packages.iter()
+                            .map(|package| package.name)
+                            .collect();
+                        format!(
+                            "Unknown package for {}; Supported packages: {}",
+                            info.name,
+                            known_packages.join(", ")
+                        )
+                    })?;
+                Some(package)
+            }
+        };
+
+        Ok(Self { info, package })
+    }
+}
+
+impl_deserialize_from_str!(Device);
+
+impl Serialize for Device {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.package {
+            Some(package) => write!(f, "{}-{}", self.info.name, package.name),
+            None => write!(f, "{}", self.info.name),
+        }
+    }
+}
+
+impl Device {
+    pub fn all() -> &'static [DeviceInfo] {
+        DEVICES
+    }
+
+    pub fn info(&self) -> &'static DeviceInfo {
+        self.info
+    }
+
+    pub fn core(&self) -> Core {
+        self.info.core
+    }
+
+    pub fn rom_words(&self) -> u16 {
+        self.info.rom_words
+    }
+
+    pub fn ram_bytes(&self) -> u16 {
+        self.info.ram_bytes
+    }
+
+    pub fn clock_sources(&self) -> &'static [ClockSource] {
+        self.info.clock_sources
+    }
+
+    /// Clock sources shared by all supported devices, used when device is not specified
+    pub fn common_clock_sources() -> &'static [ClockSource] {
+        CLOCK_SOURCES_COMMON
+    }
+
+    pub fn check_timer(&self, timer: Timer) -> Result<(), Error> {
+        if !self.info.timers.contains(&timer) {
+            return Err(Error::TimerNotAvailable(timer, self.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Stack and the application variables are not counted, `bytes` should leave room for them
+    pub fn check_ram(&self, bytes: u16) -> Result<(), Error> {
+        if bytes > self.info.ram_bytes {
+            return Err(Error::NotEnoughRam(bytes, self.to_string(), self.info.ram_bytes));
+        }
+        Ok(())
+    }
+
+    fn available_pins(&self) -> &'static [PortPins] {
+        match self.package {
+            Some(package) => package.pins,
+            None => self.info.pins,
+        }
+    }
+
+    pub fn special_pin(&self, port: Port, pin: Pin) -> Option<&'static SpecialPin> {
+        self.info.special_pins
+            .iter()
+            .find(|special| special.port == port.char() && special.pin == pin.num())
+    }
+
+    pub fn check_pin(&self, port: Port, pin: Pin) -> Result<(), Error> {
+        let port_pins = self.available_pins()
+            .iter()
+            .find(|port_pins| port_pins.port == port.char())
+            .ok_or_else(|| Error::PortNotAvailable(port, self.to_string()))?;
+
+        if port_pins.mask & (1 << pin.num()) == 0 {
+            return Err(Error::PinNotAvailable(port, pin, self.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the pin given by its name, e.g. `B0`
+    fn check_pin(device: &Device, name: &str) -> Result<(), Error> {
+        device.check_pin(name[..1].parse().unwrap(), name[1..].parse().unwrap())
+    }
+
+    #[test]
+    fn device_is_parsed_with_optional_package() {
+        let device: Device = "pfs154-s14".parse().unwrap();
+        assert_eq!(device.to_string(), "PFS154-S14");
+        assert_eq!(device.info().name, "PFS154");
+        assert!(matches!(check_pin(&device, "B0"), Err(Error::PinNotAvailable(..))));
+        assert!(check_pin(&device, "B1").is_ok());
+
+        // All pins of the die are allowed without package
+        let device: Device = "PFS154".parse().unwrap();
+        assert_eq!(device.to_string(), "PFS154");
+        assert!(check_pin(&device, "B0").is_ok());
+
+        let device: Device = "PMS150C-U06".parse().unwrap();
+        assert!(matches!(check_pin(&device, "B0"), Err(Error::PortNotAvailable(..))));
+    }
+
+    #[test]
+    fn unknown_device_or_package_is_rejected() {
+        let error = "PFS999".parse::<Device>().unwrap_err();
+        assert!(error.starts_with("Unknown device; Supported devices: PMS150C, PFS154"), "{}", error);
+        let error = "PFS154-S20".parse::<Device>().unwrap_err();
+        assert_eq!(error, "Unknown package for PFS154; Supported packages: S08, S14, S16");
+    }
+}
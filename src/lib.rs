@@ -1,3 +1,13 @@
 pub mod mcu;
+pub mod device;
 pub mod config;
-pub mod uart;
\ No newline at end of file
+pub mod uart;
+pub mod sim;
+pub mod delay;
+pub mod loopback;
+pub mod report;
+pub mod project;
+pub mod pins;
+pub mod peripheral;
+pub mod suggest;
+pub mod timer;
@@ -0,0 +1,559 @@
+//! Loopback test harness: output of the simulated `uart{N}_send` is fed into the simulated
+//! `uart{N}_receive`, optionally with baud rate mismatch and edge jitter
+
+use thiserror::Error;
+
+use crate::{
+    mcu::Parity,
+    sim::{self, Environment, IoReg, Program, Simulator},
+    uart::{self, UartGenerator},
+};
+
+/// `mov a, byte; mov PARM, a; call` on the sender side (9-bit value setup is ignored)
+const TX_CALLER_CLOCKS: u64 = 4;
+/// `call` + storing result on the receiver side
+const RX_CALLER_CLOCKS: u64 = 4;
+/// Idle line time before the first frame
+const LEAD_IN_BITS: u64 = 2;
+/// Interrupt is entered after the current instruction, so the timer-driven edges move by a clock
+const TIMER_EDGE_JITTER_CLOCKS: u64 = 1;
+/// Grid of the `loopback` command, every generated configuration of it should pass
+pub const DEFAULT_FREQUENCIES: &str = "1mhz,2mhz,4mhz,8mhz,16mhz";
+pub const DEFAULT_BAUD_RATES: &str = "9600,19200,38400,57600,115200";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Uart(#[from] uart::Error),
+    #[error(transparent)]
+    Simulation(#[from] sim::Error),
+    #[error("Loopback requires both UART TX and RX to be generated")]
+    MissingDirection,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoopbackOptions {
+    /// TX baud rate deviation from RX baud rate, percents
+    pub baud_mismatch: f64,
+    /// Max random displacement of each TX edge, clocks
+    pub jitter: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FrameError {
+    pub sent: u16,
+    pub received: u16,
+    pub result: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct LoopbackReport {
+    pub frames: usize,
+    pub frame_errors: Vec<FrameError>,
+    pub lost_frames: usize,
+    /// Edges of the sent frames which are not placed exactly on the bit boundaries
+    pub tx_timing_errors: usize,
+    /// Frames reported by the echo check as collided (there is no other driver on the line)
+    pub collisions: usize,
+}
+
+impl LoopbackReport {
+    pub fn passed(&self) -> bool {
+        self.frame_errors.is_empty()
+            && self.lost_frames == 0
+            && self.tx_timing_errors == 0
+            && self.collisions == 0
+    }
+}
+
+/// Records level of a single output pin
+struct LineRecorder {
+    port: char,
+    pin: u8,
+    idle: bool,
+    edges: Vec<(u64, bool)>,
+}
+
+impl Environment for LineRecorder {
+    fn input(&mut self, _port: char, _pin: u8, _cycle: u64) -> bool {
+        true
+    }
+
+    fn output(&mut self, port: char, pin: u8, level: Option<bool>, cycle: u64) {
+        if port != self.port || pin != self.pin {
+            return;
+        }
+        let level = level.unwrap_or(self.idle);
+        if self.edges.last().map(|(_, last)| *last) != Some(level) {
+            self.edges.push((cycle, level));
+        }
+    }
+}
+
+/// Replays recorded line on a single input pin
+struct LinePlayer {
+    port: char,
+    pin: u8,
+    idle: bool,
+    edges: Vec<(f64, bool)>,
+}
+
+impl Environment for LinePlayer {
+    fn input(&mut self, port: char, pin: u8, cycle: u64) -> bool {
+        if port != self.port || pin != self.pin {
+            return true;
+        }
+        let index = self.edges.partition_point(|(time, _)| *time <= cycle as f64);
+        match index {
+            0 => self.idle,
+            _ => self.edges[index - 1].1,
+        }
+    }
+
+    fn output(&mut self, _port: char, _pin: u8, _level: Option<bool>, _cycle: u64) {}
+}
+
+/// Deterministic xorshift generator, jitter should be reproducible between runs
+struct Jitter {
+    state: u64,
+    amplitude: u32,
+}
+
+impl Jitter {
+    fn next(&mut self) -> f64 {
+        if self.amplitude == 0 {
+            return 0.0;
+        }
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let span = 2 * self.amplitude as u64 + 1;
+        (self.state % span) as f64 - self.amplitude as f64
+    }
+}
+
+fn count_tx_timing_errors(generator: &UartGenerator, edges: &[(u64, bool)]) -> usize {
+    // Start bit + data bits + parity bit; Stop bit begins right after them
+    let parity_bits = (generator.parity() != Parity::None) as u32;
+    let frame_bits = 1 + generator.data_bits().count() as u32 + parity_bits;
+    let bit_edges: Vec<u64> = (1..=frame_bits)
+        .map(|bits| generator.bit_offset(bits) as u64)
+        .collect();
+    let stop_bit_offset = generator.bit_offset(frame_bits) as u64;
+    let frame_clocks = stop_bit_offset
+        + generator.clocks_per_stop_bit() as u64
+        + generator.idle_clocks() as u64;
+    let active = generator.tx_inverted();
+    let tolerance = if generator.timer().is_some() { TIMER_EDGE_JITTER_CLOCKS } else { 0 };
+
+    let mut errors = 0;
+    let mut frame_start: Option<u64> = None;
+    for (cycle, level) in edges.iter().copied() {
+        match frame_start {
+            Some(start) if cycle <= start + stop_bit_offset => {
+                let offset = cycle - start;
+                errors += !bit_edges.iter()
+                    .any(|edge| (*edge as i64 - offset as i64).unsigned_abs() <= tolerance) as usize;
+            }
+            _ if level == active => {
+                // Next frame should not start before the end of stop bit and idle time
+                if matches!(frame_start, Some(start) if cycle + tolerance < start + frame_clocks) {
+                    errors += 1;
+                }
+                frame_start = Some(cycle);
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Sends every data word value back-to-back and receives them on the other side; Frames are
+/// spaced by the idle time suggested by `UartGenerator::rx_turnaround`
+pub fn run(generator: &UartGenerator, options: &LoopbackOptions) -> Result<LoopbackReport, Error> {
+    let (files, uart_report) = generator.generate_with_report(false)?;
+    let program = Program::from_c_source(&files.source)?;
+
+    let clocks_per_bit = generator.clocks_per_bit() as u64;
+    // Generous limit for a single send/receive call
+    let call_limit = 2 * (10 * clocks_per_bit
+        + generator.clocks_per_stop_bit() as u64
+        + generator.idle_clocks() as u64);
+    let (tx_port, tx_pin) = generator.tx().ok_or(Error::MissingDirection)?;
+    let (rx_port, rx_pin) = generator.rx().ok_or(Error::MissingDirection)?;
+    let tx_idle = !generator.tx_inverted();
+
+    let send_function = generator.symbol_name("send");
+    // Buffered words are queued by `write`, `send` is a C wrapper around it
+    let write_function = match generator.tx_buffer_size() {
+        Some(_) => generator.symbol_name("write"),
+        None => send_function.clone(),
+    };
+    let send_argument = format!("_{}_PARM_1", write_function);
+    let receive_function = generator.symbol_name("receive");
+    let wait_ready_function = generator.symbol_name("rx_wait_ready");
+    let rx_byte = format!("_{}", generator.symbol_name("rx_byte"));
+    let rx_bit8 = format!("_{}", generator.symbol_name("rx_bit8"));
+    let data_bits = generator.data_bits().count();
+
+    let result_received = program.define("UART_RESULT_RX_RECEIVED")? as u8;
+
+    // Timer ISR runs in the background of both sides; Dividers are set by `uart{N}_init`, which is
+    // not simulated
+    let timer_isr = generator.symbol_name("timer_isr");
+    let start_timer = |sim: &mut Simulator| -> Result<(), Error> {
+        if let Some(setup) = generator.timer() {
+            sim.write_symbol(&format!("__gen_{}_divider", send_function), 1)?;
+            sim.set_periodic_interrupt(
+                &timer_isr,
+                setup.period_clocks as u64,
+                uart::ISR_LATENCY_CLOCKS as u64,
+            )?;
+        }
+        Ok(())
+    };
+    // Runtime waits of the initial baud rate are set by `uart{N}_init` as well
+    let init_runtime_waits = |sim: &mut Simulator| -> Result<(), Error> {
+        if let Some(waits) = uart_report.runtime_waits {
+            let bit_wait = format!("__gen_{}", generator.symbol_name("bit_wait"));
+            let half_bit_wait = format!("__gen_{}", generator.symbol_name("half_bit_wait"));
+            sim.write_symbol_u16(&bit_wait, u16::from_le_bytes(waits.initial_bit_wait))?;
+            sim.write_symbol_u16(&half_bit_wait, u16::from_le_bytes(waits.initial_half_bit_wait))?;
+        }
+        Ok(())
+    };
+
+    // Transmitter side
+    let mut tx = Simulator::new(&program);
+    let mut line = LineRecorder {
+        port: tx_port.char(),
+        pin: tx_pin.num(),
+        idle: tx_idle,
+        edges: vec![],
+    };
+    let tx_pin_mask = 1 << tx_pin.num();
+    if generator.half_duplex() {
+        // Open-drain line is released
+        tx.set_io(IoReg::Port(tx_port.char()), 0, &mut line);
+        tx.set_io(IoReg::PortControl(tx_port.char()), 0, &mut line);
+    } else {
+        tx.set_io(IoReg::Port(tx_port.char()), if tx_idle { tx_pin_mask } else { 0 }, &mut line);
+        tx.set_io(IoReg::PortControl(tx_port.char()), tx_pin_mask, &mut line);
+    }
+    line.edges.clear();
+    start_timer(&mut tx)?;
+    init_runtime_waits(&mut tx)?;
+    tx.wait(LEAD_IN_BITS * clocks_per_bit, &mut line)?;
+    if let Some(sync) = generator.autobaud() {
+        tx.write_symbol(&send_argument, sync.value())?;
+        tx.wait(TX_CALLER_CLOCKS, &mut line)?;
+        tx.call(&write_function, &mut line, call_limit)?;
+    }
+
+    let tx_collision = format!("_{}", generator.symbol_name("tx_collision"));
+    let mut collisions = 0;
+
+    // Receiver which can't keep up with back-to-back frames gets the idle time it asks for
+    let guard_clocks = match generator.rx_turnaround() {
+        Some(turnaround) => (turnaround.suggested_idle_bits * generator.clocks_per_bit())
+            .saturating_sub(generator.idle_clocks()) as u64,
+        None => 0,
+    };
+    let sent: Vec<u16> = (0..1 << data_bits).collect();
+    let tx_ok = match generator.tx_buffer_size() {
+        Some(_) => Some(program.define("UART_TX_OK")? as u8),
+        None => None,
+    };
+    for word in sent.iter().copied() {
+        tx.wait(guard_clocks, &mut line)?;
+        loop {
+            if data_bits > 8 {
+                tx.write_symbol_u16(&send_argument, word)?;
+            } else {
+                tx.write_symbol(&send_argument, word as u8)?;
+            }
+            tx.wait(TX_CALLER_CLOCKS, &mut line)?;
+            let result = tx.call(&write_function, &mut line, call_limit)?;
+            if tx_ok.is_none_or(|tx_ok| result == tx_ok) {
+                break;
+            }
+            // Buffer is full, retry after the ISR takes the next frame
+            tx.wait(clocks_per_bit, &mut line)?;
+        }
+        if generator.echo_check() {
+            collisions += (tx.read_symbol(&tx_collision)? != 0) as usize;
+        }
+    }
+    if let Some(size) = generator.tx_buffer_size() {
+        // Flushing the buffer
+        let tx_complete = format!("_{}", generator.symbol_name("tx_complete"));
+        let deadline = tx.cycles() + (size as u64 + 1) * call_limit;
+        while tx.read_symbol(&tx_complete)? == 0 && tx.cycles() < deadline {
+            tx.wait(clocks_per_bit, &mut line)?;
+        }
+    } else if generator.timer().is_some() {
+        // The last frame is sent by the ISR after the return
+        tx.wait(call_limit, &mut line)?;
+    }
+    let tx_end = tx.cycles();
+
+    // Line between MCUs, converted to the receiver clock
+    let scale = 1.0 / (1.0 + options.baud_mismatch / 100.0);
+    let mut jitter = Jitter { state: 0x9E37_79B9_7F4A_7C15, amplitude: options.jitter };
+    let mut player = LinePlayer {
+        port: rx_port.char(),
+        pin: rx_pin.num(),
+        idle: !generator.rx_inverted(),
+        edges: line.edges.iter()
+            .map(|(cycle, level)| (*cycle as f64 * scale + jitter.next(), *level))
+            .collect(),
+    };
+    let rx_end = (tx_end as f64 * scale) as u64 + LEAD_IN_BITS * clocks_per_bit;
+
+    // Receiver side
+    let mut rx = Simulator::new(&program);
+    let mut received = Vec::with_capacity(sent.len());
+    init_runtime_waits(&mut rx)?;
+    let synchronized = match generator.autobaud() {
+        Some(_) => {
+            rx.idle(RX_CALLER_CLOCKS);
+            let autobaud = generator.symbol_name("autobaud");
+            rx.call(&autobaud, &mut player, LEAD_IN_BITS * clocks_per_bit + call_limit)? == result_received
+        }
+        None => true,
+    };
+    if !synchronized {
+        // Frames are not received when the sync character is not accepted, they are all lost
+    } else if let Some(setup) = generator.timer() {
+        // Receiver timer runs half a period out of phase with the transmitter one
+        rx.idle(setup.period_clocks as u64 / 2);
+        start_timer(&mut rx)?;
+        let decode_function = generator.symbol_name("rx_decode");
+        let result_idle = program.define("UART_RESULT_RX_IDLE")? as u8;
+        // The frame should be taken before the next one is complete, polling each bit is enough
+        while received.len() < sent.len() && rx.cycles() < rx_end {
+            rx.wait(clocks_per_bit, &mut player)?;
+            let result = rx.call(&decode_function, &mut player, call_limit)?;
+            if result == result_idle {
+                continue;
+            }
+            let mut word = rx.read_symbol(&rx_byte)? as u16;
+            if data_bits > 8 {
+                word |= (rx.read_symbol(&rx_bit8)? as u16 & 1) << 8;
+            }
+            received.push((result, word));
+        }
+    } else if let Some(timeout) = generator.rx_timeout() {
+        // Start bit wait gives up on the quiet line after the end of the transmission
+        let receive_timeout = generator.symbol_name("receive_timeout");
+        let ticks = (call_limit / timeout.tick_clocks as u64).min(u16::MAX as u64);
+        let timeout_limit = ticks * timeout.tick_clocks as u64 + call_limit;
+        let result_idle = program.define("UART_RESULT_RX_IDLE")? as u8;
+        while received.len() < sent.len() {
+            rx.idle(RX_CALLER_CLOCKS);
+            rx.write_symbol_u16(&format!("_{}_PARM_1", receive_timeout), ticks as u16)?;
+            let result = rx.call(&receive_timeout, &mut player, timeout_limit)?;
+            if result == result_idle {
+                break;
+            }
+            let mut word = rx.read_symbol(&rx_byte)? as u16;
+            if data_bits > 8 {
+                word |= (rx.read_symbol(&rx_bit8)? as u16 & 1) << 8;
+            }
+            received.push((result, word));
+        }
+    } else if generator.rx_interrupt() {
+        // Start bit edge calls `uart{N}_rx_isr`, which receives the frame into the buffer; The
+        // interrupt is enabled by `uart{N}_init`
+        let interrupt_bit = uart::RX_INTERRUPT_PINS.iter()
+            .position(|pin| *pin == (rx_port.char(), rx_pin.num()))
+            .unwrap_or_default() as u8;
+        rx.set_io(IoReg::InterruptEnable, 1 << interrupt_bit, &mut player);
+        rx.set_pin_interrupt(
+            &generator.symbol_name("rx_isr"),
+            (rx_port.char(), rx_pin.num()),
+            interrupt_bit,
+            generator.rx_inverted(),
+            uart::ISR_LATENCY_CLOCKS as u64,
+            &mut player,
+        )?;
+        let available_function = generator.symbol_name("available");
+        let read_function = generator.symbol_name("read");
+        // Main loop empties the buffer; Invalid frames are not buffered, so they are lost
+        while received.len() < sent.len() && rx.cycles() < rx_end {
+            rx.wait(clocks_per_bit, &mut player)?;
+            let available = rx.call(&available_function, &mut player, call_limit)?;
+            for _ in 0..available {
+                let mut word = rx.call(&read_function, &mut player, call_limit)? as u16;
+                if data_bits > 8 {
+                    // High byte of the 16-bit result is returned in `p`
+                    word |= (rx.read_symbol("p")? as u16) << 8;
+                }
+                received.push((result_received, word));
+            }
+        }
+    } else {
+        while received.len() < sent.len() && rx.cycles() < rx_end {
+            rx.idle(RX_CALLER_CLOCKS);
+            let timeout = rx_end.saturating_sub(rx.cycles());
+            match rx.call(&wait_ready_function, &mut player, timeout) {
+                Ok(_) => {}
+                Err(sim::Error::CycleLimit(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+            rx.idle(RX_CALLER_CLOCKS);
+            let result = rx.call(&receive_function, &mut player, call_limit)?;
+            let mut word = rx.read_symbol(&rx_byte)? as u16;
+            if data_bits > 8 {
+                word |= (rx.read_symbol(&rx_bit8)? as u16 & 1) << 8;
+            }
+            received.push((result, word));
+        }
+    }
+
+    let frame_errors = sent.iter()
+        .zip(received.iter())
+        .filter(|(sent, (result, word))| *result != result_received || *word != **sent)
+        .map(|(sent, (result, word))| FrameError { sent: *sent, received: *word, result: *result })
+        .collect();
+
+    Ok(LoopbackReport {
+        frames: sent.len(),
+        frame_errors,
+        lost_frames: sent.len().saturating_sub(received.len()),
+        tx_timing_errors: count_tx_timing_errors(generator, &line.edges),
+        collisions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mcu::{Frequency, Parity},
+        uart::UartConfig,
+    };
+
+    fn default_bauds() -> Vec<u32> {
+        DEFAULT_BAUD_RATES.split(',').map(|baud| baud.parse().unwrap()).collect()
+    }
+
+    fn default_grid() -> Vec<(Frequency, u32)> {
+        DEFAULT_FREQUENCIES.split(',')
+            .map(|freq| freq.parse().unwrap())
+            .flat_map(|freq| default_bauds().into_iter().map(move |baud| (freq, baud)))
+            .collect()
+    }
+
+    /// Runs the default grid like the `loopback` command; The generator rejects the configurations
+    /// it can't time, the rest should pass. Expected count of the generated ones catches both
+    /// over-rejection and timing checks which are too loose
+    fn check_grid(name: &str, config: UartConfig, expected: usize) {
+        let mut generated = 0;
+        let mut rejected = Vec::new();
+        for (freq, baud) in default_grid() {
+            let config = UartConfig { frequency: Some(freq), baud: Some(baud), ..config.clone() };
+            let generator = match UartGenerator::builder().config(config).build() {
+                Ok(generator) => generator,
+                Err(e) => {
+                    rejected.push(format!("{} Hz, {} baud: {}", freq.hz(), baud, e));
+                    continue;
+                }
+            };
+            let report = run(&generator, &LoopbackOptions::default()).unwrap();
+            assert!(
+                report.passed(),
+                "{}: {} Hz, {} baud: {} errors, {} lost, {} timing, {} collisions",
+                name,
+                freq.hz(),
+                baud,
+                report.frame_errors.len(),
+                report.lost_frames,
+                report.tx_timing_errors,
+                report.collisions,
+            );
+            generated += 1;
+        }
+        assert_eq!(
+            generated,
+            expected,
+            "{}: {} configurations of the grid are generated instead of {}; Rejected:\n{}",
+            name,
+            generated,
+            expected,
+            rejected.join("\n"),
+        );
+    }
+
+    fn base() -> UartConfig {
+        UartConfig {
+            tx_port: Some("A".parse().unwrap()),
+            tx_pin: Some("3".parse().unwrap()),
+            rx_port: Some("A".parse().unwrap()),
+            rx_pin: Some("4".parse().unwrap()),
+            ..UartConfig::default()
+        }
+    }
+
+    #[test]
+    fn default_options() {
+        check_grid("8N1", base(), 21);
+        check_grid("2 stop bits", UartConfig { stop_bits: "2".parse().unwrap(), ..base() }, 21);
+        check_grid("1.5 stop bits", UartConfig { stop_bits: "1.5".parse().unwrap(), ..base() }, 21);
+        check_grid("idle bit", UartConfig { idle_bits: 1, ..base() }, 21);
+    }
+
+    #[test]
+    fn parity_and_data_bits() {
+        check_grid("8E1", UartConfig { parity: Parity::Even, ..base() }, 21);
+        check_grid("7O1", UartConfig { parity: Parity::Odd, data_bits: "7".parse().unwrap(), ..base() }, 21);
+        check_grid("5N1", UartConfig { data_bits: "5".parse().unwrap(), ..base() }, 21);
+        check_grid("9N1", UartConfig { data_bits: "9".parse().unwrap(), ..base() }, 21);
+        check_grid("9M1", UartConfig { parity: Parity::Mark, data_bits: "9".parse().unwrap(), ..base() }, 21);
+    }
+
+    #[test]
+    fn half_duplex() {
+        let half_duplex = UartConfig { half_duplex: true, rx_port: None, rx_pin: None, ..base() };
+        check_grid("half-duplex", half_duplex.clone(), 21);
+        check_grid("echo check", UartConfig { echo_check: true, ..half_duplex }, 21);
+    }
+
+    #[test]
+    fn rx_interrupt() {
+        let rx_pin = Some("0".parse().unwrap());
+        check_grid("RX interrupt", UartConfig { rx_interrupt: true, rx_pin, ..base() }, 17);
+        check_grid("9N1 RX interrupt on PB0", UartConfig {
+            rx_interrupt: true,
+            rx_port: Some("B".parse().unwrap()),
+            rx_pin,
+            data_bits: "9".parse().unwrap(),
+            ..base()
+        }, 17);
+    }
+
+    #[test]
+    fn timer() {
+        check_grid("T16", UartConfig { timer: Some("t16".parse().unwrap()), ..base() }, 7);
+        check_grid("TM2 with TX buffer", UartConfig {
+            timer: Some("tm2".parse().unwrap()),
+            tx_buffer_size: Some(4),
+            ..base()
+        }, 6);
+    }
+
+    #[test]
+    fn fractional_timing() {
+        check_grid("fractional", UartConfig { fractional_timing: true, ..base() }, 21);
+    }
+
+    #[test]
+    fn runtime_waits() {
+        check_grid("RX timeout", UartConfig { rx_timeout: true, ..base() }, 21);
+        check_grid("autobaud", UartConfig { autobaud: Some("0x55".parse().unwrap()), ..base() }, 15);
+        // Whole row of the grid is generated as the table, like `loopback --baud-table` does
+        check_grid("baud table", UartConfig { baud_table: default_bauds(), ..base() }, 5);
+        // Grid rates out of the table are rejected
+        check_grid("short baud table", UartConfig { baud_table: vec![9600, 19200], ..base() }, 8);
+    }
+}
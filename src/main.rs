@@ -1,27 +1,339 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
 use clap::Clap;
-use anyhow::Error;
+use anyhow::{anyhow, Error};
+use log::{info, LevelFilter};
 
 use freepdk_gen::{
-    config::{AppConfig, AppSubcommand},
-    uart::UartGenerator
+    config::{
+        AppConfig, AppSubcommand, BuildSubcommand, DumpTemplateSubcommand, LoopbackSubcommand,
+        SuggestSubcommand, UartCommand, UartSubcommand,
+    },
+    loopback::{self, LoopbackOptions},
+    peripheral::{
+        self, GeneratedPeripheral, McuConfig, OutputOptions, PeripheralConfig, PeripheralGenerator,
+    },
+    mcu::{Port, Pin},
+    pins::{PinAllocator, PinClaim, PinUsage},
+    project::Project,
+    suggest::{self, SuggestOptions},
+    uart::{self, UartGenerator, UartOptions},
 };
 
+/// Writes generated code and report; Paths are relative to `dir`, files without explicit paths
+/// are placed into `dir` or printed when there is no directory
+fn write_peripheral(
+    generator: &dyn PeripheralGenerator,
+    options: &OutputOptions,
+    dir: Option<&Path>,
+) -> Result<(), Error> {
+    let GeneratedPeripheral { files, report } = generator.generate(options.split)?;
+    let resolve = |path: &PathBuf| dir.map(|dir| dir.join(path)).unwrap_or_else(|| path.clone());
+    let stem = generator.name();
+
+    match (&files.header, options.output.as_ref().map(resolve)) {
+        (Some(header), output) => {
+            let output_dir = output
+                .or_else(|| dir.map(Path::to_path_buf))
+                .unwrap_or_default();
+            fs::write(output_dir.join(format!("{}.h", stem)), header)?;
+            fs::write(output_dir.join(format!("{}.c", stem)), &files.source)?;
+        }
+        (None, Some(path)) => fs::write(path, &files.source)?,
+        (None, None) => match dir {
+            Some(dir) => fs::write(dir.join(format!("{}.c", stem)), &files.source)?,
+            None => print!("{}", files.source),
+        },
+    }
+
+    if let Some(format) = options.report {
+        let rendered_report = format.render(&report)?;
+        match (options.report_file.as_ref().map(resolve), dir) {
+            (Some(path), _) => fs::write(path, rendered_report)?,
+            (None, Some(dir)) => {
+                let path = dir.join(format!("{}.report.{}", stem, format.extension()));
+                fs::write(path, rendered_report)?
+            }
+            (None, None) => println!("Report:\n{0}", rendered_report),
+        }
+    }
+    Ok(())
+}
+
+fn run_peripheral(config: &AppConfig, peripheral: &dyn PeripheralConfig) -> Result<(), Error> {
+    let mcu = McuConfig { freq: config.freq, device: config.device };
+    let generator = peripheral.build(&mcu, None)?;
+    write_peripheral(generator.as_ref(), &peripheral.output_options(), None)
+}
+
+fn run_build(config: &AppConfig, command: &BuildSubcommand) -> Result<(), Error> {
+    let project = Project::load(&command.project)?;
+
+    // Command line options take precedence over the project file
+    let mcu = McuConfig {
+        freq: config.freq.or(project.freq),
+        device: config.device.or(project.device),
+    };
+    let project_dir = command.project.parent().unwrap_or_else(|| Path::new(""));
+    let output_dir = project_dir.join(project.output.clone().unwrap_or_default());
+
+    // Whole project is validated before any file is written. Device-specific pin checks are done
+    // by each generator, only conflicts between them are left
+    let mut pins = PinAllocator::new(None);
+    let mut timers = HashMap::new();
+    let mut ram_bytes = 0;
+    let mut generated = HashSet::new();
+    let mut generators = Vec::new();
+    for peripheral in peripheral::from_project(&project) {
+        let generator = peripheral.build(&mcu, Some(project_dir))?;
+        let name = generator.name();
+        if !generated.insert(name.clone()) {
+            return Err(anyhow!("{} is defined more than once in the project", name));
+        }
+        let resources = generator.resources()?;
+        for claim in resources.pins {
+            pins.claim(claim)?;
+        }
+        for timer in resources.timers {
+            if let Some(owner) = timers.insert(timer, name.clone()) {
+                return Err(anyhow!("Timer {} is used by both {} and {}", timer, owner, name));
+            }
+        }
+        ram_bytes += resources.ram_bytes;
+        generators.push((generator, peripheral.output_options()));
+    }
+    info!("Generated code uses {} bytes of RAM in total", ram_bytes);
+    if let Some(device) = mcu.device {
+        device.check_ram(ram_bytes)?;
+    }
+
+    fs::create_dir_all(&output_dir)?;
+    for (generator, options) in generators.iter() {
+        write_peripheral(generator.as_ref(), options, Some(&output_dir))?;
+        info!("Generated {}", generator.name());
+    }
+    Ok(())
+}
+
+/// Pin of the command line, or the first free one of the device which `allowed` accepts
+fn loopback_pin(
+    pins: &mut PinAllocator,
+    function: &'static str,
+    usage: PinUsage,
+    requested: Option<(Port, Pin)>,
+    allowed: impl Fn(Port, Pin) -> bool,
+) -> Result<(Port, Pin), Error> {
+    let (port, pin) = match requested {
+        Some(requested) => requested,
+        None => {
+            let candidates = Port::all().flat_map(|port| Pin::all().map(move |pin| (port, pin)));
+            pins.find_free(usage, candidates.filter(|&(port, pin)| allowed(port, pin)))
+                .ok_or_else(|| {
+                    let option = function.to_lowercase();
+                    anyhow!("No free pin for {} on the device; Set it with --{}-port and --{}-pin", function, option, option)
+                })?
+        }
+    };
+    pins.claim(PinClaim { owner: "loopback".to_string(), function, port, pin, usage })?;
+    Ok((port, pin))
+}
+
+fn run_loopback(config: &AppConfig, command: &LoopbackSubcommand) -> Result<(), Error> {
+    let options = LoopbackOptions {
+        baud_mismatch: command.baud_mismatch,
+        jitter: command.jitter,
+    };
+
+    // RX goes first, interrupt-driven one is limited to the external interrupt pins
+    let mut pins = PinAllocator::new(config.device);
+    let rx = if command.half_duplex {
+        None
+    } else {
+        let requested = command.rx_port.zip(command.rx_pin);
+        Some(loopback_pin(&mut pins, "RX", PinUsage::Input, requested, |port, pin| {
+            !command.rx_interrupt || uart::RX_INTERRUPT_PINS.contains(&(port.char(), pin.num()))
+        })?)
+    };
+    let tx_usage = if command.half_duplex { PinUsage::OpenDrain } else { PinUsage::Output };
+    let (tx_port, tx_pin) =
+        loopback_pin(&mut pins, "TX", tx_usage, command.tx_port.zip(command.tx_pin), |_, _| true)?;
+    println!("TX {}{}{}", tx_port, tx_pin.num(), match rx {
+        Some((port, pin)) => format!(", RX {}{}", port, pin.num()),
+        None => String::new(),
+    });
+
+    let mut failed = 0;
+    println!(
+        "{:>10} {:>8} {:>6} {:>8} {:>8} {:>8} {:>10}",
+        "freq", "baud", "cpb", "errors", "lost", "timing", "collisions"
+    );
+    for freq in command.freq.iter().copied() {
+        for baud in command.baud.iter().copied() {
+            let mut builder = UartGenerator::builder()
+                .frequency(freq)
+                .baud(baud)
+                .tx_port(tx_port)
+                .tx_pin(tx_pin)
+                .uart_num(0)
+                .stop_bits(command.stop_bits)
+                .data_bits(command.data_bits)
+                .parity(command.parity)
+                .idle_bits(command.idle_bits);
+            if let Some(device) = config.device {
+                builder = builder.device(device);
+            }
+            builder = match rx {
+                Some((rx_port, rx_pin)) => builder.rx_port(rx_port).rx_pin(rx_pin),
+                None => builder.half_duplex(),
+            };
+            if command.echo_check {
+                builder = builder.echo_check();
+            }
+            if command.fractional_timing {
+                builder = builder.fractional_timing();
+            }
+            if command.rx_interrupt {
+                builder = builder.rx_interrupt();
+            }
+            if let Some(timer) = command.timer {
+                builder = builder.timer(timer);
+            }
+            if let Some(size) = command.tx_buffer_size {
+                builder = builder.tx_buffer_size(size);
+            }
+            if command.rx_timeout {
+                builder = builder.rx_timeout();
+            }
+            if let Some(sync) = command.autobaud {
+                builder = builder.autobaud(sync);
+            }
+            // Each entry is checked as the initial one, the code is the same for all of them
+            if command.baud_table {
+                builder = builder.baud_table(command.baud.clone());
+            }
+            let generator = builder.build();
+
+            let generator = match generator {
+                Ok(generator) => generator,
+                Err(e) => {
+                    println!("{:>10} {:>8} skipped: {}", freq, baud, e);
+                    continue;
+                }
+            };
+
+            let report = loopback::run(&generator, &options)?;
+            // Frames are spaced for the receiver which can't take them back-to-back
+            let spacing = match generator.rx_turnaround() {
+                Some(turnaround) => format!(" (idle bits: {})", turnaround.suggested_idle_bits),
+                None => String::new(),
+            };
+            println!(
+                "{:>10} {:>8} {:>6} {:>8} {:>8} {:>8} {:>10}{}",
+                freq,
+                baud,
+                generator.clocks_per_bit(),
+                report.frame_errors.len(),
+                report.lost_frames,
+                report.tx_timing_errors,
+                report.collisions,
+                spacing,
+            );
+            if !report.passed() {
+                failed += 1;
+            }
+        }
+    }
+
+    if failed != 0 {
+        return Err(anyhow!("Loopback test failed for {} configuration(s)", failed));
+    }
+    Ok(())
+}
+
+fn run_suggest(config: &AppConfig, command: &SuggestSubcommand) -> Result<(), Error> {
+    let options = SuggestOptions {
+        fractional_timing: command.fractional_timing,
+        max_deviation: command.max_deviation,
+        clock_tolerance: command.clock_tolerance,
+    };
+    let candidates = match (command.baud, command.freq.or(config.freq)) {
+        (Some(baud), _) => suggest::for_baud(baud, config.device, &options),
+        (None, Some(freq)) => suggest::for_frequency(freq, config.device, &options),
+        (None, None) => return Err(anyhow!("Either --freq or --baud should be specified")),
+    };
+
+    println!("{:>8} {:>10} {:>8} {:>6} {:>9}", "clock", "freq", "baud", "cpb", "deviation");
+    let mut found = false;
+    for candidate in candidates {
+        let clock = candidate.clock.as_deref().unwrap_or("-");
+        match candidate.timing {
+            Ok(timing) => {
+                found = true;
+                println!(
+                    "{:>8} {:>10} {:>8} {:>6} {:>8.2}%",
+                    clock,
+                    candidate.frequency.hz(),
+                    candidate.baud,
+                    timing.clocks_per_bit,
+                    timing.deviation_percent,
+                );
+            }
+            Err(e) if command.all => {
+                println!(
+                    "{:>8} {:>10} {:>8} rejected: {}",
+                    clock,
+                    candidate.frequency.hz(),
+                    candidate.baud,
+                    e,
+                );
+            }
+            Err(_) => {}
+        }
+    }
+
+    if !found {
+        return Err(anyhow!("No achievable combinations found"));
+    }
+    Ok(())
+}
+
+fn run_dump_template(command: &DumpTemplateSubcommand) -> Result<(), Error> {
+    let kind = peripheral::find(&command.peripheral).ok_or_else(|| {
+        let names: Vec<_> = peripheral::REGISTRY.iter().map(|kind| kind.name).collect();
+        anyhow!("Unknown peripheral {}; Available: {}", command.peripheral, names.join(", "))
+    })?;
+    print!("{}", (kind.builtin_template)());
+    Ok(())
+}
+
 fn main() -> Result<(), Error> {
+    let config: AppConfig = AppConfig::parse();
+
+    // Loopback builds lots of generators, their diagnostics would clutter the results table
+    let log_level = match &config.subcommand {
+        AppSubcommand::Loopback(_) => LevelFilter::Error,
+        AppSubcommand::Uart(UartSubcommand { command: Some(_), .. }) => LevelFilter::Error,
+        _ => LevelFilter::Info,
+    };
+
     env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(log_level)
         .format_timestamp(None)
         .format_module_path(false)
         .init();
 
-    let config: AppConfig = AppConfig::parse();
-
-    if matches!(config.subcommand, AppSubcommand::Uart(_)) {
-        let generated_data = UartGenerator::builder()
-            .load_config(&config)?
-            .build()?
-            .generate()?;
-
-        println!("Generated file:\n{0}", generated_data)
+    match &config.subcommand {
+        AppSubcommand::Loopback(command) => run_loopback(&config, command)?,
+        AppSubcommand::Build(command) => run_build(&config, command)?,
+        AppSubcommand::DumpTemplate(command) => run_dump_template(command)?,
+        AppSubcommand::Uart(UartSubcommand { command: Some(UartCommand::Suggest(command)), .. }) => {
+            run_suggest(&config, command)?
+        }
+        AppSubcommand::Uart(uart) => run_peripheral(&config, &UartOptions::from(uart))?,
     }
 
     Ok(())
@@ -1,5 +1,9 @@
 use std::str::FromStr;
-use std::fmt::{Display, Formatter};
+use std::fmt::{self, Display, Formatter};
+use std::marker::PhantomData;
+
+use serde::{Serialize, Serializer};
+use serde::de::{self, Deserializer, Visitor};
 
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct Frequency(u32);
@@ -14,7 +18,7 @@ impl FromStr for Frequency {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.find(|ch: char| !ch.is_digit(10)) {
+        match s.find(|ch: char| !ch.is_ascii_digit()) {
             None => {
                 let value: u32 = s.parse().map_err(|_| "Frequency is not a number".to_string())?;
                 Ok(Self(value))
@@ -42,6 +46,10 @@ impl FromStr for Frequency {
 }
 
 impl Frequency {
+    pub fn from_hz(hz: u32) -> Self {
+        Self(hz)
+    }
+
     pub fn hz(self) -> u32 {
         self.0
     }
@@ -79,10 +87,21 @@ impl FromStr for Port {
     }
 }
 
+impl Display for Port {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "P{}", self.0)
+    }
+}
+
 impl Port {
     pub fn char(&self) -> char {
         self.0
     }
+
+    /// All ports known to the generator; Device could have only a part of them
+    pub fn all() -> impl Iterator<Item = Self> {
+        PORTS.iter().map(|port| Self(*port))
+    }
 }
 
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
@@ -93,7 +112,7 @@ impl FromStr for Pin {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let value: u8 = s.parse().map_err(|_| "Invalid pin number".to_string())?;
-        if value > 7 as u8 {
+        if value > 7 {
             Err("Pin can't be bigger than 7".to_string())
         } else {
             Ok(Self(value))
@@ -105,10 +124,15 @@ impl Pin {
     pub fn num(&self) -> u8 {
         self.0
     }
+
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..8).map(Self)
+    }
 }
 
-#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
 pub enum StopBits {
+    #[default]
     One,
     Two,
     OneAndHalf,
@@ -127,3 +151,317 @@ impl FromStr for StopBits {
     }
 }
 
+
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub struct DataBits(u8);
+
+impl FromStr for DataBits {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: u8 = s.parse().map_err(|_| "Invalid data bits count".to_string())?;
+        if !(5..=9).contains(&value) {
+            Err("Data bits count should be in range 5..9".to_string())
+        } else {
+            Ok(Self(value))
+        }
+    }
+}
+
+impl Default for DataBits {
+    fn default() -> Self {
+        Self(8)
+    }
+}
+
+impl DataBits {
+    pub fn count(&self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+    Mark,
+    Space,
+}
+
+impl FromStr for Parity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "even" => Ok(Self::Even),
+            "odd" => Ok(Self::Odd),
+            "mark" => Ok(Self::Mark),
+            "space" => Ok(Self::Space),
+            _ => Err("Invalid parity value".to_string())
+        }
+    }
+}
+
+/// Hardware timer which could clock a generated peripheral
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub enum Timer {
+    T16,
+    Tm2,
+    Tm3,
+}
+
+impl FromStr for Timer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "t16" => Ok(Self::T16),
+            "tm2" => Ok(Self::Tm2),
+            "tm3" => Ok(Self::Tm3),
+            _ => Err("Invalid timer; Available values: t16, tm2, tm3".to_string())
+        }
+    }
+}
+
+impl Display for Timer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Timer {
+    /// Register prefix, e.g. `TM2`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::T16 => "T16",
+            Self::Tm2 => "TM2",
+            Self::Tm3 => "TM3",
+        }
+    }
+}
+
+/// Character the host sends for the baud rate detection; Both have falling edges at the start bit
+/// and at the last data bit, 8 bits apart
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Debug)]
+pub enum SyncChar {
+    X55,
+    X7F,
+}
+
+impl FromStr for SyncChar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().trim_start_matches("0x") {
+            "55" => Ok(Self::X55),
+            "7f" => Ok(Self::X7F),
+            _ => Err("Invalid sync character; Available values: 0x55, 0x7f".to_string())
+        }
+    }
+}
+
+impl SyncChar {
+    pub fn value(self) -> u8 {
+        match self {
+            Self::X55 => 0x55,
+            Self::X7F => 0x7F,
+        }
+    }
+
+    /// Falling edges after the start bit one, the last is at the start of the 8th data bit
+    pub fn falling_edges(self) -> u8 {
+        match self {
+            Self::X55 => 4,
+            Self::X7F => 1,
+        }
+    }
+}
+
+/// Baud rate or comma-separated list of them, e.g. `9600,115200`
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BaudRates(pub Vec<u32>);
+
+impl FromStr for BaudRates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|baud| baud.trim().parse::<u32>().ok().filter(|baud| *baud != 0))
+            .collect::<Option<Vec<_>>>()
+            .map(Self)
+            .ok_or_else(|| "Invalid baud rate; Expected a number or comma-separated list, e.g. 9600,115200".to_string())
+    }
+}
+
+/// Deserializes config file value through its `FromStr` implementation, the same way as command
+/// line arguments are parsed; Numbers are accepted as well, e.g. `tx_pin = 3` and `tx_pin = "3"`
+pub(crate) fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    struct FromStrVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: FromStr<Err = String>> Visitor<'de> for FromStrVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("string or number")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+            v.parse().map_err(E::custom)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+            self.visit_str(&v.to_string())
+        }
+    }
+
+    deserializer.deserialize_any(FromStrVisitor(PhantomData))
+}
+
+macro_rules! impl_deserialize_from_str {
+    ($($ty:ty),*) => {$(
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $crate::mcu::deserialize_from_str(deserializer)
+            }
+        }
+    )*};
+}
+
+pub(crate) use impl_deserialize_from_str;
+
+impl_deserialize_from_str!(Frequency, Port, Pin, StopBits, DataBits, Parity, Timer, SyncChar);
+
+/// Takes the command line form, e.g. `baud = "9600,115200"` or `baud = 9600`, as well as a list,
+/// e.g. `baud = [9600, 115200]`
+impl<'de> serde::Deserialize<'de> for BaudRates {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BaudRatesVisitor;
+
+        impl<'de> Visitor<'de> for BaudRatesVisitor {
+            type Value = BaudRates;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                f.write_str("baud rate, comma-separated string or list of them")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<BaudRates, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<BaudRates, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<BaudRates, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<BaudRates, A::Error> {
+                let mut rates = Vec::new();
+                while let Some(baud) = seq.next_element::<u32>()? {
+                    rates.push(baud.to_string());
+                }
+                self.visit_str(&rates.join(","))
+            }
+        }
+
+        deserializer.deserialize_any(BaudRatesVisitor)
+    }
+}
+
+// Serialized values are accepted back by `FromStr`
+
+impl Serialize for Frequency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl Serialize for Port {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl Serialize for Pin {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.0)
+    }
+}
+
+impl Serialize for StopBits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::One => serializer.serialize_u8(1),
+            Self::Two => serializer.serialize_u8(2),
+            Self::OneAndHalf => serializer.serialize_f64(1.5),
+        }
+    }
+}
+
+impl Serialize for DataBits {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.0)
+    }
+}
+
+impl Serialize for Parity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Self::None => "none",
+            Self::Even => "even",
+            Self::Odd => "odd",
+            Self::Mark => "mark",
+            Self::Space => "space",
+        })
+    }
+}
+
+impl Serialize for Timer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name().to_lowercase())
+    }
+}
+
+impl Serialize for SyncChar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:#04x}", self.value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_is_one_of_known_ports() {
+        assert_eq!("b".parse::<Port>().unwrap().char(), 'B');
+        assert!("Z".parse::<Port>().is_err());
+        assert!("AB".parse::<Port>().is_err());
+    }
+
+    #[test]
+    fn baud_rates_deserialize_from_string_number_and_list() {
+        let parse = |value: &str| serde_json::from_str::<BaudRates>(value).map(|rates| rates.0);
+        assert_eq!(parse("9600").unwrap(), vec![9600]);
+        assert_eq!(parse("\"9600, 19200\"").unwrap(), vec![9600, 19200]);
+        assert_eq!(parse("[9600, 19200]").unwrap(), vec![9600, 19200]);
+        assert!(parse("[]").is_err());
+        assert!(parse("[9600, 0]").is_err());
+    }
+}
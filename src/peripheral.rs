@@ -0,0 +1,112 @@
+//! Common interface of the peripheral generators
+//!
+//! Each protocol provides options type implementing `PeripheralConfig` and registers itself in
+//! `REGISTRY`; Project file entries are dispatched through the registry, so the rest of the
+//! application handles all peripherals the same way. Command line options are converted to the
+//! same options types by the application.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    device::Device,
+    mcu::Frequency,
+    pins::PinClaim,
+    project::Project,
+    report::ReportFormat,
+    uart,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Uart(#[from] uart::Error),
+    #[error("Failed to read template {}", _0.display())]
+    TemplateRead(PathBuf, #[source] std::io::Error),
+}
+
+/// MCU-wide settings shared by all peripherals
+#[derive(Clone, Copy, Default)]
+pub struct McuConfig {
+    pub freq: Option<Frequency>,
+    pub device: Option<Device>,
+}
+
+/// Where the generated code and report are written to
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OutputOptions {
+    /// Output file; Directory for the split output
+    pub output: Option<PathBuf>,
+    pub split: bool,
+    pub report: Option<ReportFormat>,
+    pub report_file: Option<PathBuf>,
+}
+
+/// Resources claimed by the generated code
+#[derive(Clone, Debug, Default)]
+pub struct Resources {
+    pub pins: Vec<PinClaim>,
+    /// Hardware timers, e.g. `T16`
+    pub timers: Vec<&'static str>,
+    pub ram_bytes: u16,
+}
+
+/// Generated code, either a single self-contained C file or a header with the implementation
+pub struct GeneratedFiles {
+    /// Public declarations, present only for the split output
+    pub header: Option<String>,
+    pub source: String,
+}
+
+pub struct GeneratedPeripheral {
+    pub files: GeneratedFiles,
+    /// Generator-specific report data, rendered with the requested `ReportFormat`
+    pub report: serde_json::Value,
+}
+
+pub trait PeripheralGenerator {
+    /// Instance name, e.g. `uart0`; Used as the generated files name
+    fn name(&self) -> String;
+
+    fn resources(&self) -> Result<Resources, Error>;
+
+    fn generate(&self, split: bool) -> Result<GeneratedPeripheral, Error>;
+}
+
+/// Peripheral options from the command line or the project file
+pub trait PeripheralConfig {
+    /// Validates the options and creates the generator; Relative paths are resolved against `dir`
+    fn build(&self, mcu: &McuConfig, dir: Option<&Path>)
+        -> Result<Box<dyn PeripheralGenerator>, Error>;
+
+    fn output_options(&self) -> OutputOptions;
+}
+
+pub struct PeripheralKind {
+    pub name: &'static str,
+    /// Options of all instances described in the project file
+    pub from_project: fn(&Project) -> Vec<&dyn PeripheralConfig>,
+    /// Template printed by the `dump-template` subcommand
+    pub builtin_template: fn() -> String,
+}
+
+pub const REGISTRY: &[PeripheralKind] = &[
+    PeripheralKind {
+        name: "uart",
+        from_project: |project| project.uarts.iter().map(|uart| uart as _).collect(),
+        builtin_template: uart::builtin_template,
+    },
+];
+
+/// Finds the peripheral kind by its name, e.g. `uart`
+pub fn find(name: &str) -> Option<&'static PeripheralKind> {
+    REGISTRY.iter().find(|kind| kind.name == name)
+}
+
+/// Collects options of all peripheral instances of the project
+pub fn from_project(project: &Project) -> Vec<&dyn PeripheralConfig> {
+    REGISTRY.iter().flat_map(|kind| (kind.from_project)(project)).collect()
+}
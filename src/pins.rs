@@ -0,0 +1,190 @@
+//! Pin allocation checker
+//!
+//! Generators report pins they use as claims; Allocator catches pins claimed twice and pins whose
+//! special function on the selected device conflicts with the claimed usage.
+
+use std::fmt::{Display, Formatter};
+
+use log::warn;
+use thiserror::Error;
+
+use crate::{
+    device::{self, Device},
+    mcu::{Port, Pin},
+};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PinUsage {
+    Input,
+    /// Push-pull output
+    Output,
+    /// Line is only pulled low, released line is read as input
+    OpenDrain,
+}
+
+/// Pin used by a generated peripheral
+#[derive(Clone, Debug)]
+pub struct PinClaim {
+    /// Peripheral instance, e.g. `uart0`
+    pub owner: String,
+    /// Function of the pin within the peripheral, e.g. `TX`
+    pub function: &'static str,
+    pub port: Port,
+    pub pin: Pin,
+    pub usage: PinUsage,
+}
+
+impl Display for PinClaim {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.owner, self.function)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Pin {}{} is claimed by both {} and {}", _0.port, _0.pin.num(), _0, _1)]
+    Conflict(PinClaim, PinClaim),
+    #[error(
+        "Pin {}{} can't be used as output by {}; It is used for {} on {}",
+        _0.port, _0.pin.num(), _0, _1, _2
+    )]
+    InputOnly(PinClaim, &'static str, String),
+    #[error(transparent)]
+    Device(#[from] device::Error),
+}
+
+pub struct PinAllocator {
+    device: Option<Device>,
+    claims: Vec<PinClaim>,
+}
+
+impl PinAllocator {
+    /// Without device only conflicts between the claims are checked
+    pub fn new(device: Option<Device>) -> Self {
+        Self { device, claims: Vec::new() }
+    }
+
+    pub fn claim(&mut self, claim: PinClaim) -> Result<(), Error> {
+        if let Some(device) = self.device {
+            device.check_pin(claim.port, claim.pin)?;
+
+            if let Some(special) = device.special_pin(claim.port, claim.pin) {
+                if special.input_only && claim.usage == PinUsage::Output {
+                    return Err(Error::InputOnly(claim, special.function, device.to_string()));
+                }
+                warn!(
+                    "Pin {}{} used by {} is also used for {} on {}",
+                    claim.port,
+                    claim.pin.num(),
+                    claim,
+                    special.function,
+                    device
+                );
+            }
+        }
+
+        if let Some(existing) = self.claims
+            .iter()
+            .find(|existing| existing.port == claim.port && existing.pin == claim.pin)
+        {
+            return Err(Error::Conflict(existing.clone(), claim));
+        }
+
+        self.claims.push(claim);
+        Ok(())
+    }
+
+    /// First of `candidates` which is present on the device, not claimed yet and could be used
+    /// this way; Pins with a special function are taken only when there is nothing else
+    pub fn find_free(
+        &self,
+        usage: PinUsage,
+        candidates: impl Iterator<Item = (Port, Pin)>,
+    ) -> Option<(Port, Pin)> {
+        candidates
+            .filter(|&(port, pin)| !self.claims.iter().any(|claim| claim.port == port && claim.pin == pin))
+            .filter_map(|(port, pin)| match self.device {
+                Some(device) => {
+                    device.check_pin(port, pin).ok()?;
+                    let special = device.special_pin(port, pin);
+                    if special.is_some_and(|special| special.input_only && usage == PinUsage::Output) {
+                        return None;
+                    }
+                    Some((special.is_some(), port, pin))
+                }
+                None => Some((false, port, pin)),
+            })
+            .min_by_key(|&(special, _, _)| special)
+            .map(|(_, port, pin)| (port, pin))
+    }
+
+    pub fn claims(&self) -> &[PinClaim] {
+        &self.claims
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pin by its name, e.g. `A4`
+    fn pin(name: &str) -> (Port, Pin) {
+        (name[..1].parse().unwrap(), name[1..].parse().unwrap())
+    }
+
+    fn claim(owner: &str, function: &'static str, name: &str, usage: PinUsage) -> PinClaim {
+        let (port, pin) = pin(name);
+        PinClaim { owner: owner.to_string(), function, port, pin, usage }
+    }
+
+    #[test]
+    fn pin_claimed_twice_is_conflict() {
+        let mut allocator = PinAllocator::new(None);
+        allocator.claim(claim("uart0", "TX", "A4", PinUsage::Output)).unwrap();
+        allocator.claim(claim("uart0", "RX", "A3", PinUsage::Input)).unwrap();
+        match allocator.claim(claim("uart1", "RX", "A4", PinUsage::Input)) {
+            Err(Error::Conflict(existing, claim)) => {
+                assert_eq!(existing.to_string(), "uart0 TX");
+                assert_eq!(claim.to_string(), "uart1 RX");
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(allocator.claims().len(), 2);
+    }
+
+    #[test]
+    fn input_only_pin_is_rejected_for_output() {
+        let mut allocator = PinAllocator::new(Some("PFS154".parse().unwrap()));
+        assert!(matches!(
+            allocator.claim(claim("uart0", "TX", "A5", PinUsage::Output)),
+            Err(Error::InputOnly(_, "reset and programming voltage", _))
+        ));
+        // Special function which doesn't conflict with the usage is only a warning
+        allocator.claim(claim("uart0", "RX", "A5", PinUsage::Input)).unwrap();
+        allocator.claim(claim("uart0", "TX", "A6", PinUsage::Output)).unwrap();
+    }
+
+    #[test]
+    fn pin_missing_in_package_is_rejected() {
+        let mut allocator = PinAllocator::new(Some("PFS154-S08".parse().unwrap()));
+        assert!(matches!(
+            allocator.claim(claim("uart0", "TX", "B0", PinUsage::Output)),
+            Err(Error::Device(device::Error::PortNotAvailable(..)))
+        ));
+    }
+
+    #[test]
+    fn free_pin_prefers_pins_without_special_function() {
+        let mut allocator = PinAllocator::new(Some("PFS154".parse().unwrap()));
+        allocator.claim(claim("uart0", "TX", "A0", PinUsage::Output)).unwrap();
+        let candidates = ["A0", "A3", "A4", "A5"];
+        let free = |usage, candidates: &[&str]| {
+            allocator.find_free(usage, candidates.iter().map(|name| pin(name)))
+        };
+        assert_eq!(free(PinUsage::Output, &candidates), Some(pin("A4")));
+        assert_eq!(free(PinUsage::Output, &candidates[..2]), Some(pin("A3")));
+        // Input-only pin is left only for inputs
+        assert_eq!(free(PinUsage::Output, &candidates[3..]), None);
+        assert_eq!(free(PinUsage::Input, &candidates[3..]), Some(pin("A5")));
+    }
+}
@@ -0,0 +1,138 @@
+//! Project file describing all peripherals generated for a single firmware
+//!
+//! Project could be written in TOML or YAML, the format is selected by the file extension:
+//!
+//! ```toml
+//! device = "PFS154-S08"
+//! freq = "8mhz"
+//! output = "generated"
+//!
+//! [[uart]]
+//! baud = 9600
+//! tx_port = "A"
+//! tx_pin = 3
+//! ```
+//!
+//! Each `uart` entry takes the same options as the `uart` subcommand, see `UartOptions`.
+
+use std::{fs, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    device::Device,
+    mcu::Frequency,
+    uart::UartOptions,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read project file")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid TOML project file")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid YAML project file")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Unknown project file format; Supported extensions: toml, yaml, yml")]
+    UnknownFormat,
+    #[error("Unknown field `{}` of the uart entry", _0)]
+    UnknownField(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    pub device: Option<Device>,
+    pub freq: Option<Frequency>,
+    /// Directory for the generated files, relative to the project file
+    pub output: Option<PathBuf>,
+    #[serde(default, rename = "uart")]
+    pub uarts: Vec<UartOptions>,
+}
+
+impl Project {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content, extension.as_deref())
+    }
+
+    /// Parses the project in the format given by the file extension
+    fn parse(content: &str, extension: Option<&str>) -> Result<Self, Error> {
+        let project: Self = match extension {
+            Some("toml") => toml::from_str(content)?,
+            Some("yaml") | Some("yml") => serde_yaml::from_str(content)?,
+            _ => return Err(Error::UnknownFormat),
+        };
+        if let Some(field) = project.uarts.iter().flat_map(UartOptions::unknown_fields).next() {
+            return Err(Error::UnknownField(field.to_string()));
+        }
+        Ok(project)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_yaml_projects_are_parsed() {
+        let toml = r#"
+            device = "PFS154-S08"
+            freq = "8mhz"
+            output = "generated"
+
+            [[uart]]
+            baud = 9600
+            tx_port = "A"
+            tx_pin = 3
+
+            [[uart]]
+            baud = [9600, 115200]
+            rx_port = "A"
+            rx_pin = 4
+            split = true
+        "#;
+        let project = Project::parse(toml, Some("toml")).unwrap();
+        assert_eq!(project.device.unwrap().to_string(), "PFS154-S08");
+        assert_eq!(project.freq.unwrap().hz(), 8_000_000);
+        assert_eq!(project.output, Some(PathBuf::from("generated")));
+        assert_eq!(project.uarts.len(), 2);
+        assert_eq!(project.uarts[0].baud.as_ref().unwrap().0, vec![9600]);
+        assert_eq!(project.uarts[0].config.tx_pin.unwrap().num(), 3);
+        assert!(project.uarts[0].config.rx_port.is_none());
+        assert_eq!(project.uarts[1].baud.as_ref().unwrap().0, vec![9600, 115200]);
+        assert!(project.uarts[1].output.split);
+
+        let yaml = "
+freq: 8mhz
+uart:
+  - baud: 19200
+    tx_port: A
+    tx_pin: 4
+";
+        let project = Project::parse(yaml, Some("yml")).unwrap();
+        assert!(project.device.is_none());
+        assert_eq!(project.uarts[0].baud.as_ref().unwrap().0, vec![19200]);
+        assert_eq!(project.uarts[0].config.tx_pin.unwrap().num(), 4);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let project = |content: &str| Project::parse(content, Some("toml"));
+        assert!(matches!(project("frequency = \"8mhz\""), Err(Error::Toml(_))));
+        assert!(matches!(
+            project("[[uart]]\nbaud = 9600\ntx_prot = \"A\""),
+            Err(Error::UnknownField(field)) if field == "tx_prot"
+        ));
+        // MCU settings are common for the project
+        assert!(matches!(
+            project("[[uart]]\nbaud = 9600\nfrequency = \"8mhz\""),
+            Err(Error::UnknownField(field)) if field == "frequency"
+        ));
+        assert!(matches!(Project::parse("", Some("json")), Err(Error::UnknownFormat)));
+    }
+}
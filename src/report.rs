@@ -0,0 +1,40 @@
+//! Machine-readable reports about the generated code
+
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::mcu::impl_deserialize_from_str;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ReportFormat {
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            _ => Err("Invalid report format".to_string())
+        }
+    }
+}
+
+impl_deserialize_from_str!(ReportFormat);
+
+impl ReportFormat {
+    /// Extension of the report files
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+        }
+    }
+
+    pub fn render(self, report: &impl Serialize) -> Result<String, serde_json::Error> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(report),
+        }
+    }
+}
@@ -0,0 +1,836 @@
+//! Cycle-accurate simulator of the Padauk core subset used by generated code
+//!
+//! Generated C file is used as-is: all `__asm` blocks are extracted and assembled, while C
+//! `#define`s, global variables and function parameters become program symbols. Non-asm parts of
+//! the generated code (e.g. init functions) are not simulated; use `Simulator::set_io` and
+//! `Simulator::write_symbol` to prepare the state instead.
+
+mod asm;
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+pub use asm::{AluOp, Function, Instruction, IoReg, Loc, Program, UnaryOp, Value};
+
+const FLAG_Z: u8 = 0;
+const FLAG_C: u8 = 1;
+const FLAG_AC: u8 = 2;
+const FLAG_OV: u8 = 3;
+
+const RETURN_TO_CALLER: usize = usize::MAX;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Generated code assembly failed: {}", _0)]
+    Parse(String),
+    #[error("Function {} is not found in generated code", _0)]
+    UnknownFunction(String),
+    #[error("Symbol {} is not found in generated code", _0)]
+    UnknownSymbol(String),
+    #[error("Stack corruption detected at `{}`", _0)]
+    StackCorruption(String),
+    #[error("Execution reached the end of naked function after `{}`", _0)]
+    NakedFunctionEnd(String),
+    #[error("Program counter is out of program bounds ({})", _0)]
+    InvalidAddress(usize),
+    #[error("Function did not return in {} cycles", _0)]
+    CycleLimit(u64),
+}
+
+/// External circuit connected to the MCU pins
+pub trait Environment {
+    /// Level applied to the input pin at the given cycle
+    fn input(&mut self, port: char, pin: u8, cycle: u64) -> bool;
+    /// Called when the pin drive state changes; `None` means the pin was switched to input
+    fn output(&mut self, port: char, pin: u8, level: Option<bool>, cycle: u64);
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Edge {
+    pub port: char,
+    pub pin: u8,
+    pub level: Option<bool>,
+    pub cycle: u64,
+}
+
+/// Environment with constant input levels which records all output changes
+#[derive(Default, Debug)]
+pub struct PinRecorder {
+    inputs: BTreeMap<(char, u8), bool>,
+    pub edges: Vec<Edge>,
+}
+
+impl PinRecorder {
+    pub fn set_input(&mut self, port: char, pin: u8, level: bool) {
+        self.inputs.insert((port, pin), level);
+    }
+
+    /// Edges of the single pin, excluding drive state changes without level change
+    pub fn pin_edges(&self, port: char, pin: u8) -> Vec<Edge> {
+        let mut last_level = None;
+        self.edges.iter()
+            .filter(|edge| edge.port == port && edge.pin == pin)
+            .filter(|edge| {
+                let changed = last_level != Some(edge.level);
+                last_level = Some(edge.level);
+                changed
+            })
+            .copied()
+            .collect()
+    }
+}
+
+impl Environment for PinRecorder {
+    fn input(&mut self, port: char, pin: u8, _cycle: u64) -> bool {
+        // Floating pins are considered pulled up
+        self.inputs.get(&(port, pin)).copied().unwrap_or(true)
+    }
+
+    fn output(&mut self, port: char, pin: u8, level: Option<bool>, cycle: u64) {
+        self.edges.push(Edge { port, pin, level, cycle });
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum StackEntry {
+    Return(usize),
+    AccumulatorAndFlags(u8, u8),
+    /// Interrupted code; Restored by the return from the interrupt function. SDCC interrupt
+    /// handler saves the `p` pseudo-register as well
+    Interrupt { address: usize, a: u8, flags: u8, p: [u8; 2] },
+}
+
+/// Timer interrupt which calls the function every `period` clocks
+#[derive(Clone, Copy, Debug)]
+struct PeriodicInterrupt {
+    entry: usize,
+    period: u64,
+    latency: u64,
+    next: u64,
+}
+
+/// External interrupt of an input pin: the selected edge sets the `INTRQ` bit, the function is
+/// called while the `INTEN` bit is set
+#[derive(Clone, Copy, Debug)]
+struct PinInterrupt {
+    entry: usize,
+    port: char,
+    pin: u8,
+    bit: u8,
+    /// Pin level after the edge, which requests the interrupt
+    edge: bool,
+    latency: u64,
+    level: bool,
+}
+
+pub struct Simulator<'p> {
+    program: &'p Program,
+    pc: usize,
+    cycles: u64,
+    a: u8,
+    flags: u8,
+    ram: [u8; 256],
+    io: BTreeMap<IoReg, u8>,
+    drive: BTreeMap<(char, u8), Option<bool>>,
+    stack: Vec<StackEntry>,
+    interrupts_enabled: bool,
+    periodic_interrupt: Option<PeriodicInterrupt>,
+    pin_interrupt: Option<PinInterrupt>,
+}
+
+impl<'p> Simulator<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            pc: 0,
+            cycles: 0,
+            a: 0,
+            flags: 0,
+            ram: [0; 256],
+            io: BTreeMap::new(),
+            drive: BTreeMap::new(),
+            stack: Vec::new(),
+            interrupts_enabled: false,
+            periodic_interrupt: None,
+            pin_interrupt: None,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Advances time without executing any code (e.g. to emulate the caller's code)
+    pub fn idle(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    /// Calls `function` every `period` clocks from now on, as the application interrupt handler
+    /// would do `latency` clocks after the timer interrupt; Enables interrupts
+    pub fn set_periodic_interrupt(
+        &mut self,
+        function: &str,
+        period: u64,
+        latency: u64,
+    ) -> Result<(), Error> {
+        let entry = self.program.function(function)?.entry;
+        self.periodic_interrupt = Some(PeriodicInterrupt {
+            entry,
+            period,
+            latency,
+            next: self.cycles + period,
+        });
+        self.interrupts_enabled = true;
+        Ok(())
+    }
+
+    /// Calls `function` on the `edge` (level after it) of the input pin, as the application
+    /// interrupt handler would do `latency` clocks after the request; The request is set in the
+    /// `INTRQ` register at `bit`, the interrupt is taken while the same bit of `INTEN` is set and
+    /// should be cleared by the function. Enables interrupts
+    pub fn set_pin_interrupt(
+        &mut self,
+        function: &str,
+        (port, pin): (char, u8),
+        bit: u8,
+        edge: bool,
+        latency: u64,
+        env: &mut impl Environment,
+    ) -> Result<(), Error> {
+        let entry = self.program.function(function)?.entry;
+        let level = env.input(port, pin, self.cycles);
+        self.pin_interrupt = Some(PinInterrupt { entry, port, pin, bit, edge, latency, level });
+        self.interrupts_enabled = true;
+        Ok(())
+    }
+
+    /// Advances time like `idle`, but serves the interrupts meanwhile; The interrupt pin is
+    /// sampled every clock
+    pub fn wait(&mut self, cycles: u64, env: &mut impl Environment) -> Result<(), Error> {
+        let deadline = self.cycles + cycles;
+        loop {
+            self.sample_interrupt_pin(env);
+            if self.interrupt_due() {
+                self.enter_interrupt(RETURN_TO_CALLER);
+                while self.pc != RETURN_TO_CALLER {
+                    self.step(env)?;
+                }
+                continue;
+            }
+            if self.cycles >= deadline {
+                break;
+            }
+            self.cycles = match (self.pin_interrupt, self.periodic_interrupt) {
+                (Some(_), _) => self.cycles + 1,
+                (None, Some(interrupt)) if self.interrupts_enabled => interrupt.next.min(deadline),
+                _ => deadline,
+            };
+        }
+        Ok(())
+    }
+
+    fn enter_interrupt(&mut self, address: usize) {
+        let (entry, latency) = match (self.periodic_interrupt.as_mut(), self.pin_interrupt) {
+            (Some(interrupt), _) if interrupt.next <= self.cycles => {
+                interrupt.next += interrupt.period;
+                (interrupt.entry, interrupt.latency)
+            }
+            (_, Some(interrupt)) => (interrupt.entry, interrupt.latency),
+            _ => return,
+        };
+        let p = self.pseudo_register();
+        self.stack.push(StackEntry::Interrupt { address, a: self.a, flags: self.flags, p });
+        self.pc = entry;
+        self.cycles += latency;
+        self.interrupts_enabled = false;
+    }
+
+    /// Sets the interrupt request on the edge of the interrupt pin since the last sample
+    fn sample_interrupt_pin(&mut self, env: &mut impl Environment) {
+        let interrupt = match self.pin_interrupt.as_mut() {
+            Some(interrupt) => interrupt,
+            None => return,
+        };
+        let level = env.input(interrupt.port, interrupt.pin, self.cycles);
+        let bit = interrupt.bit;
+        let requested = level != interrupt.level && level == interrupt.edge;
+        interrupt.level = level;
+        if requested {
+            let request = self.io(IoReg::InterruptRequest);
+            self.io.insert(IoReg::InterruptRequest, request | 1 << bit);
+        }
+    }
+
+    /// `p` is used by the generated code for indirect access, if at all
+    fn pseudo_register(&self) -> [u8; 2] {
+        self.read_symbol_u16("p").map(u16::to_le_bytes).unwrap_or_default()
+    }
+
+    fn interrupt_due(&self) -> bool {
+        let requests = self.io(IoReg::InterruptRequest) & self.io(IoReg::InterruptEnable);
+        self.interrupts_enabled
+            && (matches!(self.periodic_interrupt, Some(interrupt) if interrupt.next <= self.cycles)
+                || matches!(self.pin_interrupt, Some(interrupt) if requests & 1 << interrupt.bit != 0))
+    }
+
+    pub fn read_symbol(&self, symbol: &str) -> Result<u8, Error> {
+        Ok(self.ram[self.program.symbol(symbol)? as usize])
+    }
+
+    pub fn write_symbol(&mut self, symbol: &str, value: u8) -> Result<(), Error> {
+        self.ram[self.program.symbol(symbol)? as usize] = value;
+        Ok(())
+    }
+
+    /// Reads little-endian 16-bit variable
+    pub fn read_symbol_u16(&self, symbol: &str) -> Result<u16, Error> {
+        let address = self.program.symbol(symbol)? as usize;
+        Ok(u16::from_le_bytes([self.ram[address], self.ram[(address + 1) % 256]]))
+    }
+
+    /// Writes little-endian 16-bit variable
+    pub fn write_symbol_u16(&mut self, symbol: &str, value: u16) -> Result<(), Error> {
+        let address = self.program.symbol(symbol)? as usize;
+        let [low, high] = value.to_le_bytes();
+        self.ram[address] = low;
+        self.ram[(address + 1) % 256] = high;
+        Ok(())
+    }
+
+    pub fn io(&self, reg: IoReg) -> u8 {
+        self.io.get(&reg).copied().unwrap_or(0)
+    }
+
+    pub fn set_io(&mut self, reg: IoReg, value: u8, env: &mut impl Environment) {
+        self.write_io(reg, value, env);
+    }
+
+    /// Calls function and runs it until return; Returns accumulator value
+    pub fn call(
+        &mut self,
+        function: &str,
+        env: &mut impl Environment,
+        cycle_limit: u64,
+    ) -> Result<u8, Error> {
+        let function = self.program.function(function)?;
+        let deadline = self.cycles + cycle_limit;
+        let stack_depth = self.stack.len();
+
+        self.stack.push(StackEntry::Return(RETURN_TO_CALLER));
+        self.pc = function.entry;
+
+        while self.pc != RETURN_TO_CALLER {
+            if self.cycles > deadline {
+                self.stack.truncate(stack_depth);
+                return Err(Error::CycleLimit(cycle_limit));
+            }
+            if self.interrupt_due() {
+                self.enter_interrupt(self.pc);
+            }
+            self.step(env)?;
+        }
+
+        Ok(self.a)
+    }
+
+    fn current_line(&self) -> String {
+        self.program.lines.get(self.pc).cloned().unwrap_or_default()
+    }
+
+    fn flag(&self, bit: u8) -> bool {
+        self.flags & (1 << bit) != 0
+    }
+
+    fn set_flag(&mut self, bit: u8, value: bool) {
+        if value {
+            self.flags |= 1 << bit;
+        } else {
+            self.flags &= !(1 << bit);
+        }
+    }
+
+    fn port_value(&mut self, port: char, mask: u8, env: &mut impl Environment) -> u8 {
+        let latch = self.io(IoReg::Port(port));
+        let outputs = self.io(IoReg::PortControl(port));
+        let mut value = latch & outputs;
+        for pin in 0..8 {
+            let bit = 1 << pin;
+            if mask & bit != 0 && outputs & bit == 0 && env.input(port, pin, self.cycles) {
+                value |= bit;
+            }
+        }
+        value & mask
+    }
+
+    fn read_masked(&mut self, loc: Loc, mask: u8, env: &mut impl Environment) -> u8 {
+        match loc {
+            Loc::A => self.a,
+            Loc::Mem(address) => self.ram[address as usize],
+            Loc::Io(IoReg::Flags) => self.flags,
+            Loc::Io(IoReg::Port(port)) => self.port_value(port, mask, env),
+            Loc::Io(reg) => self.io(reg),
+        }
+    }
+
+    fn read(&mut self, loc: Loc, env: &mut impl Environment) -> u8 {
+        self.read_masked(loc, 0xFF, env)
+    }
+
+    fn value(&mut self, value: Value, env: &mut impl Environment) -> u8 {
+        match value {
+            Value::Imm(value) => value,
+            Value::Loc(loc) => self.read(loc, env),
+        }
+    }
+
+    fn write_io(&mut self, reg: IoReg, value: u8, env: &mut impl Environment) {
+        self.io.insert(reg, value);
+
+        let port = match reg {
+            IoReg::Port(port) | IoReg::PortControl(port) => port,
+            _ => return,
+        };
+
+        let latch = self.io(IoReg::Port(port));
+        let outputs = self.io(IoReg::PortControl(port));
+        for pin in 0..8 {
+            let bit = 1 << pin;
+            let level = if outputs & bit != 0 { Some(latch & bit != 0) } else { None };
+            let previous = self.drive.insert((port, pin), level).unwrap_or(None);
+            if previous != level {
+                env.output(port, pin, level, self.cycles);
+            }
+        }
+    }
+
+    fn write(&mut self, loc: Loc, value: u8, env: &mut impl Environment) {
+        match loc {
+            Loc::A => self.a = value,
+            Loc::Mem(address) => self.ram[address as usize] = value,
+            Loc::Io(IoReg::Flags) => self.flags = value & 0x0F,
+            Loc::Io(reg) => self.write_io(reg, value, env),
+        }
+    }
+
+    fn add(&mut self, lhs: u8, rhs: u8, carry: bool) -> u8 {
+        let carry = carry as u16;
+        let result = lhs as u16 + rhs as u16 + carry;
+        let value = result as u8;
+        self.set_flag(FLAG_Z, value == 0);
+        self.set_flag(FLAG_C, result > 0xFF);
+        self.set_flag(FLAG_AC, (lhs & 0x0F) as u16 + (rhs & 0x0F) as u16 + carry > 0x0F);
+        self.set_flag(FLAG_OV, (!(lhs ^ rhs) & (lhs ^ value)) & 0x80 != 0);
+        value
+    }
+
+    fn sub(&mut self, lhs: u8, rhs: u8, borrow: bool) -> u8 {
+        let borrow = borrow as i16;
+        let result = lhs as i16 - rhs as i16 - borrow;
+        let value = result as u8;
+        self.set_flag(FLAG_Z, value == 0);
+        self.set_flag(FLAG_C, result < 0);
+        self.set_flag(FLAG_AC, ((lhs & 0x0F) as i16 - (rhs & 0x0F) as i16 - borrow) < 0);
+        self.set_flag(FLAG_OV, ((lhs ^ rhs) & (lhs ^ value)) & 0x80 != 0);
+        value
+    }
+
+    fn unary(&mut self, op: UnaryOp, value: u8) -> u8 {
+        let carry = self.flag(FLAG_C);
+        match op {
+            UnaryOp::Inc => self.add(value, 1, false),
+            UnaryOp::Dec => self.sub(value, 1, false),
+            UnaryOp::Clear => 0,
+            UnaryOp::Not => {
+                self.set_flag(FLAG_Z, !value == 0);
+                !value
+            }
+            UnaryOp::Neg => {
+                let result = value.wrapping_neg();
+                self.set_flag(FLAG_Z, result == 0);
+                result
+            }
+            UnaryOp::Sr | UnaryOp::Src => {
+                self.set_flag(FLAG_C, value & 0x01 != 0);
+                let msb = if op == UnaryOp::Src && carry { 0x80 } else { 0 };
+                (value >> 1) | msb
+            }
+            UnaryOp::Sl | UnaryOp::Slc => {
+                self.set_flag(FLAG_C, value & 0x80 != 0);
+                let lsb = if op == UnaryOp::Slc && carry { 0x01 } else { 0 };
+                (value << 1) | lsb
+            }
+            UnaryOp::Swap => value.rotate_left(4),
+        }
+    }
+
+    fn pop(&mut self) -> Result<StackEntry, Error> {
+        self.stack.pop().ok_or_else(|| Error::StackCorruption(self.current_line()))
+    }
+
+    fn ret(&mut self) -> Result<usize, Error> {
+        match self.pop()? {
+            StackEntry::Return(address) => Ok(address),
+            StackEntry::Interrupt { address, a, flags, p } => {
+                self.a = a;
+                self.flags = flags;
+                if self.program.symbol("p").is_ok() {
+                    self.write_symbol_u16("p", u16::from_le_bytes(p))?;
+                }
+                self.interrupts_enabled = true;
+                Ok(address)
+            }
+            _ => Err(Error::StackCorruption(self.current_line())),
+        }
+    }
+
+    /// Executes single instruction
+    pub fn step(&mut self, env: &mut impl Environment) -> Result<(), Error> {
+        self.sample_interrupt_pin(env);
+        let instruction = *self.program.instructions
+            .get(self.pc)
+            .ok_or(Error::InvalidAddress(self.pc))?;
+
+        let mut next = self.pc + 1;
+        let mut cycles = 1;
+
+        match instruction {
+            Instruction::Nop => {}
+            Instruction::Mov(dst, src) => {
+                let value = self.value(src, env);
+                if dst == Loc::A {
+                    if let Value::Loc(Loc::Mem(_)) | Value::Loc(Loc::Io(_)) = src {
+                        self.set_flag(FLAG_Z, value == 0);
+                    }
+                }
+                self.write(dst, value, env);
+            }
+            Instruction::Xch(loc) => {
+                let value = self.read(loc, env);
+                let a = self.a;
+                self.write(loc, a, env);
+                self.a = value;
+            }
+            Instruction::Alu(op, dst, src) => {
+                let lhs = self.read(dst, env);
+                let rhs = self.value(src, env);
+                let carry = self.flag(FLAG_C);
+                let result = match op {
+                    AluOp::Add => self.add(lhs, rhs, false),
+                    AluOp::Addc => self.add(lhs, rhs, carry),
+                    AluOp::Sub => self.sub(lhs, rhs, false),
+                    AluOp::Subc => self.sub(lhs, rhs, carry),
+                    AluOp::And | AluOp::Or | AluOp::Xor => {
+                        let result = match op {
+                            AluOp::And => lhs & rhs,
+                            AluOp::Or => lhs | rhs,
+                            _ => lhs ^ rhs,
+                        };
+                        self.set_flag(FLAG_Z, result == 0);
+                        result
+                    }
+                };
+                self.write(dst, result, env);
+            }
+            Instruction::Unary(op, loc) => {
+                let value = self.read(loc, env);
+                let result = self.unary(op, value);
+                self.write(loc, result, env);
+            }
+            Instruction::StepSkipZero(op, loc) => {
+                let value = self.read(loc, env);
+                let result = self.unary(op, value);
+                self.write(loc, result, env);
+                if result == 0 {
+                    next += 1;
+                    cycles = 2;
+                }
+            }
+            Instruction::CompareSkip { lhs, rhs, skip_if_equal } => {
+                let lhs = self.read(lhs, env);
+                let rhs = self.value(rhs, env);
+                self.sub(lhs, rhs, false);
+                if (lhs == rhs) == skip_if_equal {
+                    next += 1;
+                    cycles = 2;
+                }
+            }
+            Instruction::SetBit(loc, bit, value) => {
+                // Bit operations on ports are read-modify-write of the output latch
+                let current = match loc {
+                    Loc::Io(IoReg::Port(port)) => self.io(IoReg::Port(port)),
+                    _ => self.read(loc, env),
+                };
+                let updated = if value { current | (1 << bit) } else { current & !(1 << bit) };
+                self.write(loc, updated, env);
+            }
+            Instruction::TestBitSkip(loc, bit, skip_if) => {
+                let value = self.read_masked(loc, 1 << bit, env);
+                if (value & (1 << bit) != 0) == skip_if {
+                    next += 1;
+                    cycles = 2;
+                }
+            }
+            Instruction::Goto(address) => {
+                next = address;
+                cycles = 2;
+            }
+            Instruction::Call(address) => {
+                self.stack.push(StackEntry::Return(next));
+                next = address;
+                cycles = 2;
+            }
+            Instruction::Ret(value) => {
+                if let Some(value) = value {
+                    self.a = value;
+                }
+                next = self.ret()?;
+                cycles = 2;
+            }
+            Instruction::FunctionEnd => {
+                if self.program.naked[self.pc] {
+                    let last_line = self.program.lines[self.pc.saturating_sub(1)].clone();
+                    return Err(Error::NakedFunctionEnd(last_line));
+                }
+                next = self.ret()?;
+                cycles = 2;
+            }
+            Instruction::Reti => {
+                next = self.ret()?;
+                cycles = 2;
+                self.interrupts_enabled = true;
+            }
+            Instruction::Pushaf => {
+                self.stack.push(StackEntry::AccumulatorAndFlags(self.a, self.flags));
+            }
+            Instruction::Popaf => match self.pop()? {
+                StackEntry::AccumulatorAndFlags(a, flags) => {
+                    self.a = a;
+                    self.flags = flags;
+                }
+                _ => return Err(Error::StackCorruption(self.current_line())),
+            },
+            Instruction::Engint => self.interrupts_enabled = true,
+            Instruction::Disgint => self.interrupts_enabled = false,
+            Instruction::Stt16(_) => {}
+            Instruction::Idxm { pointer, load } => {
+                cycles = 2;
+                let address = self.read(pointer, env) as usize;
+                if load {
+                    self.a = self.ram[address];
+                } else {
+                    self.ram[address] = self.a;
+                }
+            }
+        }
+
+        self.cycles += cycles;
+        self.pc = next;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mcu::{Frequency, Parity},
+        uart::{UartGenerator, UartGeneratorBuilder},
+    };
+
+    /// Wraps instructions into a naked function `test`
+    fn program(body: &str) -> Program {
+        let source = format!(
+            "uint8_t value;\n\nvoid test(void) __naked {{\n    __asm\n{}\n    __endasm;\n}}\n",
+            body,
+        );
+        Program::from_c_source(&source).unwrap()
+    }
+
+    /// Cycles taken by the `test` function, including its return
+    fn run(program: &Program, value: u8) -> (u64, u8) {
+        let mut sim = Simulator::new(program);
+        sim.write_symbol("_value", value).unwrap();
+        let mut env = PinRecorder::default();
+        let a = sim.call("test", &mut env, 1000).unwrap();
+        (sim.cycles(), a)
+    }
+
+    #[test]
+    fn instruction_cycles() {
+        assert_eq!(run(&program("    ret"), 0).0, 2);
+        assert_eq!(run(&program("    nop\n    mov a, #1\n    ret"), 0).0, 4);
+        assert_eq!(run(&program("    goto .+1\n    ret"), 0).0, 4);
+        assert_eq!(run(&program("    call 0001$\n    ret\n    0001$:\n    ret"), 0).0, 6);
+        assert_eq!(run(&program("    pushaf\n    popaf\n    ret"), 0).0, 4);
+    }
+
+    #[test]
+    fn skip_takes_two_cycles_and_skips_instruction() {
+        let test_bit = program("    t0sn _value, #0\n    ret #1\n    ret #2");
+        assert_eq!(run(&test_bit, 0), (4, 2));
+        assert_eq!(run(&test_bit, 1), (3, 1));
+
+        let decrement = program("    dzsn _value\n    ret #1\n    ret #2");
+        assert_eq!(run(&decrement, 1), (4, 2));
+        assert_eq!(run(&decrement, 2), (3, 1));
+
+        let compare = program("    mov a, #5\n    ceqsn a, _value\n    ret #1\n    ret #2");
+        assert_eq!(run(&compare, 5), (5, 2));
+        assert_eq!(run(&compare, 4), (4, 1));
+    }
+
+    #[test]
+    fn loop_cycles() {
+        // 3T per iteration, 2T for the last decrement
+        let wait = program(
+            "    mov a, #4\n    mov _value, a\n    0001$:\n    dzsn _value\n    goto 0001$\n    ret"
+        );
+        assert_eq!(run(&wait, 0).0, 2 + 3 * 3 + 2 + 2);
+    }
+
+    fn uart(
+        freq: u32,
+        baud: u32,
+        configure: impl Fn(UartGeneratorBuilder) -> UartGeneratorBuilder,
+    ) -> UartGenerator {
+        let builder = UartGenerator::builder()
+            .frequency(Frequency::from_hz(freq))
+            .baud(baud)
+            .tx_port("A".parse().unwrap())
+            .tx_pin("3".parse().unwrap())
+            .rx_port("A".parse().unwrap())
+            .rx_pin("4".parse().unwrap())
+            .uart_num(0);
+        configure(builder).build().unwrap()
+    }
+
+    fn configurations() -> Vec<UartGenerator> {
+        vec![
+            uart(8_000_000, 115_200, |builder| builder),
+            uart(1_000_000, 9_600, |builder| builder),
+            uart(8_000_000, 57_600, |builder| builder.parity(Parity::Even)),
+            uart(8_000_000, 57_600, |builder| builder.parity(Parity::Mark)),
+            uart(8_000_000, 57_600, |builder| builder.data_bits("9".parse().unwrap())),
+            uart(8_000_000, 57_600, |builder| builder.data_bits("5".parse().unwrap())),
+            uart(8_000_000, 38_400, |builder| builder.stop_bits("2".parse().unwrap())),
+            uart(16_000_000, 115_200, |builder| builder.fractional_timing()),
+        ]
+    }
+
+    /// Frame bits of the word with parity, starting from the start bit
+    fn frame(generator: &UartGenerator, word: u16) -> Vec<bool> {
+        let data_bits = generator.data_bits().count();
+        let mut bits = vec![false];
+        bits.extend((0..data_bits).map(|bit| word & (1 << bit) != 0));
+        let ones = bits.iter().filter(|bit| **bit).count();
+        match generator.parity() {
+            Parity::None => {}
+            Parity::Even => bits.push(ones % 2 == 1),
+            Parity::Odd => bits.push(ones % 2 == 0),
+            Parity::Mark => bits.push(true),
+            Parity::Space => bits.push(false),
+        }
+        bits
+    }
+
+    #[test]
+    fn uart_send_bit_edges() {
+        for generator in configurations() {
+            let program = Program::from_c_source(&generator.generate().unwrap()).unwrap();
+            let data_bits = generator.data_bits().count();
+            // Alternating bits, so each bit boundary has an edge
+            let word = 0x155 & ((1 << data_bits) - 1);
+            let bits = frame(&generator, word);
+
+            let mut sim = Simulator::new(&program);
+            let mut env = PinRecorder::default();
+            sim.set_io(IoReg::Port('A'), 1 << 3, &mut env);
+            sim.set_io(IoReg::PortControl('A'), 1 << 3, &mut env);
+            env.edges.clear();
+            sim.write_symbol_u16("_uart0_send_PARM_1", word).unwrap();
+            sim.call("uart0_send", &mut env, 100_000).unwrap();
+
+            let mut level = true;
+            let mut expected = Vec::new();
+            for (index, bit) in bits.iter().chain([true].iter()).enumerate() {
+                if *bit != level {
+                    expected.push((generator.bit_offset(index as u32) as u64, *bit));
+                    level = *bit;
+                }
+            }
+            let edges: Vec<_> = env.pin_edges('A', 3).iter()
+                .map(|edge| (edge.cycle - env.edges[0].cycle, edge.level.unwrap()))
+                .collect();
+            assert_eq!(edges, expected, "{} baud, {} data bits", generator.baud(), data_bits);
+
+            // Return (counted by the simulator) and the next call (2T) complete the stop bit
+            let stop_bit_start = env.edges[0].cycle + generator.bit_offset(bits.len() as u32) as u64;
+            assert_eq!(
+                sim.cycles() + 2 - stop_bit_start,
+                generator.clocks_per_stop_bit() as u64 + generator.idle_clocks() as u64,
+                "{} baud, {} data bits", generator.baud(), data_bits,
+            );
+        }
+    }
+
+    /// Plays the frame starting from cycle 0 and records the cycles of RX samples
+    struct FramePlayer {
+        bits: Vec<bool>,
+        bit_offsets: Vec<u64>,
+        samples: Vec<u64>,
+    }
+
+    impl Environment for FramePlayer {
+        fn input(&mut self, _port: char, _pin: u8, cycle: u64) -> bool {
+            self.samples.push(cycle);
+            let bit = self.bit_offsets.partition_point(|offset| *offset <= cycle);
+            self.bits.get(bit.wrapping_sub(1)).copied().unwrap_or(true)
+        }
+
+        fn output(&mut self, _port: char, _pin: u8, _level: Option<bool>, _cycle: u64) {}
+    }
+
+    #[test]
+    fn uart_receive_samples() {
+        for generator in configurations() {
+            let program = Program::from_c_source(&generator.generate().unwrap()).unwrap();
+            let data_bits = generator.data_bits().count();
+            let expected_clocks_per_bit = generator.frequency().hz() as f64 / generator.baud() as f64;
+            let half_bit = (expected_clocks_per_bit / 2.0).round() as u64;
+
+            for word in [0x155 & ((1 << data_bits) - 1), (1 << data_bits) - 1, 0] {
+                let bits = frame(&generator, word);
+                let mut player = FramePlayer {
+                    bits: bits.clone(),
+                    bit_offsets: (0..=bits.len() as u32)
+                        .map(|bit| generator.bit_offset(bit) as u64)
+                        .collect(),
+                    samples: Vec::new(),
+                };
+                let mut sim = Simulator::new(&program);
+                let result = sim.call("uart0_receive", &mut player, 100_000).unwrap();
+                assert_eq!(result as i64, program.define("UART_RESULT_RX_RECEIVED").unwrap());
+                let mut received = sim.read_symbol("_uart0_rx_byte").unwrap() as u16;
+                if data_bits > 8 {
+                    received |= (sim.read_symbol("_uart0_rx_bit8").unwrap() as u16 & 1) << 8;
+                }
+                assert_eq!(received, word);
+
+                // Early check, then the samples of each bit
+                let samples = &player.samples;
+                assert_eq!(samples.len(), bits.len() + 2);
+                assert_eq!(samples[0], 0);
+                let start_sample = samples[1];
+                assert_eq!(start_sample, half_bit, "{} baud, {} data bits", generator.baud(), data_bits);
+                for (bit, sample) in samples[1..].iter().enumerate() {
+                    assert_eq!(*sample - start_sample, generator.bit_offset(bit as u32) as u64);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,720 @@
+use std::collections::HashMap;
+
+use super::Error;
+
+const RAM_SIZE: u16 = 256;
+
+/// IO register referenced from generated code by `<NAME>_ADDR` symbol
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum IoReg {
+    Flags,
+    Port(char),
+    PortControl(char),
+    PortPullUp(char),
+    PortDigitalInput(char),
+    InterruptEnable,
+    InterruptRequest,
+}
+
+impl IoReg {
+    fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("f") {
+            return Some(Self::Flags);
+        }
+
+        let name = name.strip_suffix("_ADDR")?;
+        match name {
+            "INTEN" => return Some(Self::InterruptEnable),
+            "INTRQ" => return Some(Self::InterruptRequest),
+            _ => {}
+        }
+        let mut chars = name.chars();
+        if chars.next()? != 'P' {
+            return None;
+        }
+        let port = chars.next().filter(|ch| ch.is_ascii_uppercase())?;
+        match chars.as_str() {
+            "" => Some(Self::Port(port)),
+            "C" => Some(Self::PortControl(port)),
+            "PH" => Some(Self::PortPullUp(port)),
+            "DIER" => Some(Self::PortDigitalInput(port)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Loc {
+    A,
+    Mem(u16),
+    Io(IoReg),
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Value {
+    Imm(u8),
+    Loc(Loc),
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum AluOp {
+    Add,
+    Addc,
+    Sub,
+    Subc,
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum UnaryOp {
+    Inc,
+    Dec,
+    Clear,
+    Not,
+    Neg,
+    Sr,
+    Sl,
+    Src,
+    Slc,
+    Swap,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Instruction {
+    Nop,
+    Mov(Loc, Value),
+    Xch(Loc),
+    Alu(AluOp, Loc, Value),
+    Unary(UnaryOp, Loc),
+    /// `izsn`/`dzsn`; Increments or decrements location and skips next instruction on zero
+    StepSkipZero(UnaryOp, Loc),
+    /// `ceqsn`/`cneqsn`; Skips next instruction when comparison result matches `skip_if_equal`
+    CompareSkip { lhs: Loc, rhs: Value, skip_if_equal: bool },
+    SetBit(Loc, u8, bool),
+    /// `t0sn`/`t1sn`; Skips next instruction when bit value equals `skip_if`
+    TestBitSkip(Loc, u8, bool),
+    Goto(usize),
+    Call(usize),
+    Ret(Option<u8>),
+    Reti,
+    Pushaf,
+    Popaf,
+    Engint,
+    Disgint,
+    /// Stores the 16-bit word to the T16 counter; T16 itself is not simulated
+    Stt16(Loc),
+    /// `idxm a, p`/`idxm p, a`; Loads A from or stores it to RAM addressed by the 16-bit pointer
+    Idxm { pointer: Loc, load: bool },
+    /// Placed after the last instruction of each function; Acts as `ret` for regular functions
+    FunctionEnd,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Function {
+    pub entry: usize,
+    pub naked: bool,
+}
+
+/// Assembled `__asm` blocks of the generated C file
+#[derive(Debug, Default)]
+pub struct Program {
+    pub(super) instructions: Vec<Instruction>,
+    pub(super) lines: Vec<String>,
+    pub(super) naked: Vec<bool>,
+    functions: HashMap<String, Function>,
+    symbols: HashMap<String, u16>,
+    ram_bytes: u16,
+    defines: HashMap<String, i64>,
+}
+
+struct SourceLine {
+    function: usize,
+    text: String,
+    mnemonic: String,
+    operands: Vec<String>,
+}
+
+struct AsmFunction {
+    name: String,
+    naked: bool,
+    body: String,
+}
+
+fn strip_comment(line: &str) -> &str {
+    let line = match line.find(';') {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+    }
+}
+
+/// C lines keep `;`, which starts a comment only in the assembly
+fn strip_c_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(pos) => line[..pos].trim_end(),
+        None => line,
+    }
+}
+
+fn identifier_before_paren(header: &str) -> Option<&str> {
+    let before_paren = &header[..header.find('(')?];
+    let name = before_paren
+        .trim_end()
+        .rsplit(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+        .next()?;
+    if name.is_empty() { None } else { Some(name) }
+}
+
+fn c_type_size(c_type: &str) -> u16 {
+    match c_type {
+        "uint16_t" | "int16_t" => 2,
+        "uint32_t" | "int32_t" => 4,
+        _ => 1,
+    }
+}
+
+/// Collects `#define` constants, global variables and `__asm` function bodies from C source
+fn parse_c_source(
+    source: &str,
+    defines: &mut HashMap<String, i64>,
+    sizes: &mut HashMap<String, u16>,
+) -> Result<Vec<AsmFunction>, Error> {
+    let mut functions = Vec::new();
+    let mut current_header: Option<String> = None;
+    let mut asm_body: Option<String> = None;
+    let mut depth = 0usize;
+
+    for raw_line in source.lines() {
+        let line = raw_line.trim();
+
+        if let Some(body) = asm_body.as_mut() {
+            if line.starts_with("__endasm") {
+                let header = current_header.clone().unwrap_or_default();
+                let name = identifier_before_paren(&header)
+                    .ok_or_else(|| Error::Parse(format!("Can't find function for asm block: {}", header)))?
+                    .to_string();
+                functions.push(AsmFunction {
+                    name,
+                    naked: header.contains("__naked"),
+                    body: asm_body.take().unwrap(),
+                });
+            } else {
+                body.push_str(raw_line);
+                body.push('\n');
+            }
+            continue;
+        }
+
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                if let Ok(value) = parse_number(value) {
+                    defines.insert(name.to_string(), value);
+                }
+            }
+            continue;
+        }
+
+        if line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        if line == "__asm" {
+            asm_body = Some(String::new());
+            continue;
+        }
+
+        let code = strip_c_comment(line);
+
+        if depth == 0 && code.contains('(') && code.ends_with('{') {
+            // Function parameters are passed through `_<function>_PARM_<n>` variables
+            if let Some(name) = identifier_before_paren(code) {
+                let params = &code[code.find('(').unwrap() + 1..code.rfind(')').unwrap_or(code.len())];
+                for (index, param) in params.split(',').enumerate() {
+                    let param = param.trim();
+                    if param.is_empty() || param == "void" {
+                        continue;
+                    }
+                    let c_type = param.split_whitespace().next().unwrap_or_default();
+                    sizes.insert(format!("_{}_PARM_{}", name, index + 1), c_type_size(c_type));
+                }
+            }
+            current_header = Some(code.to_string());
+        } else if depth == 0 && code.ends_with(';') && !code.contains('(') && !code.starts_with("typedef") {
+            // Global variable declaration, e.g. `uint8_t name;` or `uint8_t name[4];`
+            let declaration = code.trim_end_matches(';');
+            let mut parts = declaration.split_whitespace().filter(|part| {
+                !matches!(*part, "static" | "volatile" | "extern" | "const")
+            });
+            if let (Some(c_type), Some(declarator)) = (parts.next(), parts.next()) {
+                let (name, count) = match declarator.find('[') {
+                    Some(pos) => {
+                        let count = declarator[pos + 1..].trim_end_matches(']');
+                        (&declarator[..pos], parse_number(count).unwrap_or(1) as u16)
+                    }
+                    None => (declarator, 1),
+                };
+                sizes.insert(format!("_{}", name), c_type_size(c_type) * count);
+            }
+        }
+
+        depth += code.matches('{').count();
+        depth = depth.saturating_sub(code.matches('}').count());
+    }
+
+    Ok(functions)
+}
+
+fn parse_number(s: &str) -> Result<i64, Error> {
+    let s = s.trim();
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2)
+    } else {
+        s.parse()
+    };
+    parsed.map_err(|_| Error::Parse(format!("Invalid number: {}", s)))
+}
+
+fn split_operands(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|operand| operand.trim().to_string())
+        .filter(|operand| !operand.is_empty())
+        .collect()
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(ch) if ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Splits `symbol+offset` memory references; Offset could be negative, so the resolved address
+/// should be checked against the RAM bounds
+fn split_offset(s: &str) -> Result<(&str, i64), Error> {
+    match s.find(['+', '-']) {
+        Some(pos) if s[..pos].trim() != "" => {
+            let offset = parse_number(&s[pos + 1..])?;
+            let offset = if s.as_bytes()[pos] == b'-' { -offset } else { offset };
+            Ok((s[..pos].trim(), offset))
+        }
+        _ => Ok((s.trim(), 0)),
+    }
+}
+
+struct Assembler<'a> {
+    defines: &'a HashMap<String, i64>,
+    symbols: &'a HashMap<String, u16>,
+}
+
+impl<'a> Assembler<'a> {
+    fn eval(&self, expr: &str) -> Result<i64, Error> {
+        let expr = expr.trim();
+        let expr = expr.strip_prefix('(').and_then(|e| e.strip_suffix(')')).unwrap_or(expr);
+
+        // Evaluate left to right sum of terms
+        let mut total = 0i64;
+        let mut sign = 1i64;
+        let mut term_start = 0usize;
+        let bytes = expr.as_bytes();
+        for pos in 0..=bytes.len() {
+            if pos == bytes.len() || ((bytes[pos] == b'+' || bytes[pos] == b'-') && pos != 0) {
+                let term = expr[term_start..pos].trim();
+                let value = if let Some(value) = self.defines.get(term) {
+                    *value
+                } else if let Some(address) = self.symbols.get(term) {
+                    *address as i64
+                } else {
+                    parse_number(term)?
+                };
+                total += sign * value;
+                if pos < bytes.len() {
+                    sign = if bytes[pos] == b'-' { -1 } else { 1 };
+                }
+                term_start = pos + 1;
+            }
+        }
+        Ok(total)
+    }
+
+    fn immediate(&self, operand: &str) -> Result<u8, Error> {
+        let expr = operand.strip_prefix('#')
+            .ok_or_else(|| Error::Parse(format!("Expected immediate value: {}", operand)))?;
+        Ok(self.eval(expr)? as u8)
+    }
+
+    fn loc(&self, operand: &str) -> Result<Loc, Error> {
+        if operand.eq_ignore_ascii_case("a") {
+            return Ok(Loc::A);
+        }
+        if let Some(io) = IoReg::parse(operand) {
+            return Ok(Loc::Io(io));
+        }
+        let (symbol, offset) = split_offset(operand)?;
+        let address = self.symbols.get(symbol)
+            .ok_or_else(|| Error::Parse(format!("Unknown operand: {}", operand)))?;
+        let address = *address as i64 + offset;
+        if !(0..RAM_SIZE as i64).contains(&address) {
+            return Err(Error::Parse(format!("Address of {} is out of RAM", operand)));
+        }
+        Ok(Loc::Mem(address as u16))
+    }
+
+    fn value(&self, operand: &str) -> Result<Value, Error> {
+        if operand.starts_with('#') {
+            Ok(Value::Imm(self.immediate(operand)?))
+        } else {
+            Ok(Value::Loc(self.loc(operand)?))
+        }
+    }
+
+    fn bit(&self, loc: Loc, operand: &str) -> Result<u8, Error> {
+        let bit = match (loc, operand.to_ascii_lowercase().as_str()) {
+            (Loc::Io(IoReg::Flags), "z") => 0,
+            (Loc::Io(IoReg::Flags), "c") => 1,
+            (Loc::Io(IoReg::Flags), "ac") => 2,
+            (Loc::Io(IoReg::Flags), "ov") => 3,
+            _ => self.immediate(operand)?,
+        };
+        if bit > 7 {
+            return Err(Error::Parse(format!("Invalid bit number: {}", operand)));
+        }
+        Ok(bit)
+    }
+}
+
+fn memory_symbols(operands: &[String]) -> impl Iterator<Item = Result<(&str, i64), Error>> {
+    operands.iter()
+        .filter(|operand| !operand.starts_with('#') && !operand.starts_with('.'))
+        .map(|operand| split_offset(operand))
+}
+
+fn unary_op(mnemonic: &str) -> Option<UnaryOp> {
+    Some(match mnemonic {
+        "inc" => UnaryOp::Inc,
+        "dec" => UnaryOp::Dec,
+        "clear" => UnaryOp::Clear,
+        "not" => UnaryOp::Not,
+        "neg" => UnaryOp::Neg,
+        "sr" => UnaryOp::Sr,
+        "sl" => UnaryOp::Sl,
+        "src" => UnaryOp::Src,
+        "slc" => UnaryOp::Slc,
+        "swap" => UnaryOp::Swap,
+        _ => return None,
+    })
+}
+
+fn alu_op(mnemonic: &str) -> Option<AluOp> {
+    Some(match mnemonic {
+        "add" => AluOp::Add,
+        "addc" => AluOp::Addc,
+        "sub" => AluOp::Sub,
+        "subc" => AluOp::Subc,
+        "and" => AluOp::And,
+        "or" => AluOp::Or,
+        "xor" => AluOp::Xor,
+        _ => return None,
+    })
+}
+
+impl Program {
+    /// Extracts and assembles all `__asm` blocks from the generated C source
+    pub fn from_c_source(source: &str) -> Result<Self, Error> {
+        let mut defines = HashMap::new();
+        let mut sizes = HashMap::new();
+        let asm_functions = parse_c_source(source, &mut defines, &mut sizes)?;
+
+        // First pass: split lines, collect labels and memory symbols
+        let mut lines: Vec<SourceLine> = Vec::new();
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        let mut functions = HashMap::new();
+
+        for (function_index, asm_function) in asm_functions.iter().enumerate() {
+            let entry = lines.len();
+            functions.insert(
+                asm_function.name.clone(),
+                Function { entry, naked: asm_function.naked }
+            );
+            labels.insert(format!("_{}", asm_function.name), entry);
+
+            for raw_line in asm_function.body.lines() {
+                let mut code = strip_comment(raw_line).trim();
+
+                while let Some(colon) = code.find(':') {
+                    let label = code[..colon].trim();
+                    let label = if label.ends_with('$') {
+                        format!("{}@{}", label, function_index)
+                    } else {
+                        label.to_string()
+                    };
+                    if labels.insert(label.clone(), lines.len()).is_some() {
+                        return Err(Error::Parse(format!("Duplicate label: {}", label)));
+                    }
+                    code = code[colon + 1..].trim();
+                }
+
+                if code.is_empty() {
+                    continue;
+                }
+
+                let (mnemonic, operands) = match code.find(char::is_whitespace) {
+                    Some(pos) => (&code[..pos], split_operands(&code[pos..])),
+                    None => (code, vec![]),
+                };
+
+                lines.push(SourceLine {
+                    function: function_index,
+                    text: code.to_string(),
+                    mnemonic: mnemonic.to_ascii_lowercase(),
+                    operands,
+                });
+            }
+
+            // SDCC emits `ret` at the end of regular functions
+            lines.push(SourceLine {
+                function: function_index,
+                text: "ret ; implicit".to_string(),
+                mnemonic: String::new(),
+                operands: vec![],
+            });
+        }
+
+        for line in lines.iter() {
+            if matches!(line.mnemonic.as_str(), "goto" | "call") {
+                continue;
+            }
+            // T16 counter is loaded from a word, indirect access takes a word pointer
+            let width = if matches!(line.mnemonic.as_str(), "stt16" | "idxm") { 2 } else { 1 };
+            for symbol in memory_symbols(&line.operands) {
+                let (symbol, offset) = symbol?;
+                if is_identifier(symbol)
+                    && IoReg::parse(symbol).is_none()
+                    && !symbol.eq_ignore_ascii_case("a")
+                    && !defines.contains_key(symbol)
+                {
+                    // Symbol size is extended by the positive offsets, negative ones refer to the
+                    // preceding symbols
+                    let size = sizes.entry(symbol.to_string()).or_insert(1);
+                    *size = (*size).max((offset + width).clamp(1, RAM_SIZE as i64) as u16);
+                }
+            }
+        }
+
+        let mut symbol_names: Vec<_> = sizes.keys().cloned().collect();
+        symbol_names.sort();
+        let mut symbols = HashMap::new();
+        let mut next_address = 0u16;
+        for name in symbol_names {
+            symbols.insert(name.clone(), next_address);
+            next_address += sizes[&name];
+        }
+        if next_address > RAM_SIZE {
+            return Err(Error::Parse(format!("Program requires {} bytes of RAM", next_address)));
+        }
+        // `p` pseudo-register for indirect access belongs to the SDCC runtime
+        let ram_bytes = next_address - sizes.get("p").copied().unwrap_or(0);
+
+        // Second pass: parse instructions
+        let assembler = Assembler { defines: &defines, symbols: &symbols };
+        let mut instructions = Vec::with_capacity(lines.len());
+        let mut texts = Vec::with_capacity(lines.len());
+        let mut naked = Vec::with_capacity(lines.len());
+
+        for (address, line) in lines.iter().enumerate() {
+            let instruction = Self::parse_instruction(&assembler, &labels, address, line)
+                .map_err(|e| match e {
+                    Error::Parse(message) => Error::Parse(format!("{} (at `{}`)", message, line.text)),
+                    e => e,
+                })?;
+            instructions.push(instruction);
+            texts.push(line.text.clone());
+            naked.push(asm_functions[line.function].naked);
+        }
+
+        Ok(Self {
+            instructions,
+            lines: texts,
+            naked,
+            functions,
+            symbols,
+            ram_bytes,
+            defines,
+        })
+    }
+
+    fn parse_instruction(
+        assembler: &Assembler,
+        labels: &HashMap<String, usize>,
+        address: usize,
+        line: &SourceLine,
+    ) -> Result<Instruction, Error> {
+        let operands = &line.operands;
+        let operand = |index: usize| -> Result<&str, Error> {
+            operands.get(index)
+                .map(|s| s.as_str())
+                .ok_or_else(|| Error::Parse("Missing operand".into()))
+        };
+        let target = |label: &str| -> Result<usize, Error> {
+            if let Some(relative) = label.strip_prefix('.') {
+                let offset = if relative.is_empty() { 0 } else { parse_number(relative.trim_start_matches('+'))? };
+                return Ok((address as i64 + offset) as usize);
+            }
+            let local = format!("{}@{}", label, line.function);
+            labels.get(&local)
+                .or_else(|| labels.get(label))
+                .copied()
+                .ok_or_else(|| Error::Parse(format!("Unknown label: {}", label)))
+        };
+
+        let mnemonic = line.mnemonic.as_str();
+        let instruction = match mnemonic {
+            "" => Instruction::FunctionEnd,
+            "nop" => Instruction::Nop,
+            "mov" => Instruction::Mov(assembler.loc(operand(0)?)?, assembler.value(operand(1)?)?),
+            "xch" => Instruction::Xch(assembler.loc(operand(0)?)?),
+            "ceqsn" | "cneqsn" => Instruction::CompareSkip {
+                lhs: assembler.loc(operand(0)?)?,
+                rhs: assembler.value(operand(1)?)?,
+                skip_if_equal: mnemonic == "ceqsn",
+            },
+            "izsn" => Instruction::StepSkipZero(UnaryOp::Inc, assembler.loc(operand(0)?)?),
+            "dzsn" => Instruction::StepSkipZero(UnaryOp::Dec, assembler.loc(operand(0)?)?),
+            "set0" | "set1" => {
+                let loc = assembler.loc(operand(0)?)?;
+                Instruction::SetBit(loc, assembler.bit(loc, operand(1)?)?, mnemonic == "set1")
+            }
+            "t0sn" | "t1sn" => {
+                let loc = assembler.loc(operand(0)?)?;
+                Instruction::TestBitSkip(loc, assembler.bit(loc, operand(1)?)?, mnemonic == "t1sn")
+            }
+            "goto" => Instruction::Goto(target(operand(0)?)?),
+            "call" => Instruction::Call(target(operand(0)?)?),
+            "ret" => match operands.first() {
+                Some(value) => Instruction::Ret(Some(assembler.immediate(value)?)),
+                None => Instruction::Ret(None),
+            },
+            "reti" => Instruction::Reti,
+            "pushaf" => Instruction::Pushaf,
+            "popaf" => Instruction::Popaf,
+            "engint" => Instruction::Engint,
+            "stt16" => Instruction::Stt16(assembler.loc(operand(0)?)?),
+            "idxm" => match (assembler.loc(operand(0)?)?, assembler.loc(operand(1)?)?) {
+                (Loc::A, pointer) => Instruction::Idxm { pointer, load: true },
+                (pointer, Loc::A) => Instruction::Idxm { pointer, load: false },
+                _ => return Err(Error::Parse("idxm requires accumulator operand".to_string())),
+            },
+            "disgint" => Instruction::Disgint,
+            _ => {
+                if let (Some(op @ (AluOp::Addc | AluOp::Subc)), 1) = (alu_op(mnemonic), operands.len()) {
+                    // `addc m`/`subc m` add or subtract only the carry
+                    Instruction::Alu(op, assembler.loc(operand(0)?)?, Value::Imm(0))
+                } else if let Some(op) = alu_op(mnemonic) {
+                    Instruction::Alu(op, assembler.loc(operand(0)?)?, assembler.value(operand(1)?)?)
+                } else if let Some(op) = unary_op(mnemonic) {
+                    Instruction::Unary(op, assembler.loc(operand(0)?)?)
+                } else {
+                    return Err(Error::Parse(format!("Unsupported instruction: {}", mnemonic)));
+                }
+            }
+        };
+
+        Ok(instruction)
+    }
+
+    pub fn function(&self, name: &str) -> Result<Function, Error> {
+        self.functions.get(name).copied().ok_or_else(|| Error::UnknownFunction(name.to_string()))
+    }
+
+    pub fn symbol(&self, name: &str) -> Result<u16, Error> {
+        self.symbols.get(name).copied().ok_or_else(|| Error::UnknownSymbol(name.to_string()))
+    }
+
+    /// Value of numeric `#define` from the C source
+    pub fn define(&self, name: &str) -> Result<i64, Error> {
+        self.defines.get(name).copied().ok_or_else(|| Error::UnknownSymbol(name.to_string()))
+    }
+
+    /// Upper bound of the function execution time, clocks; Each instruction is counted once with
+    /// its longest timing, so it is valid only for code without backward jumps
+    pub fn max_clocks(&self, name: &str) -> Result<u32, Error> {
+        let entry = self.function(name)?.entry;
+        let mut clocks = 0;
+        for instruction in &self.instructions[entry..] {
+            clocks += match instruction {
+                Instruction::Goto(_)
+                | Instruction::Call(_)
+                | Instruction::Ret(_)
+                | Instruction::Reti
+                | Instruction::StepSkipZero(..)
+                | Instruction::CompareSkip { .. }
+                | Instruction::TestBitSkip(..)
+                | Instruction::Idxm { .. }
+                | Instruction::FunctionEnd => 2,
+                _ => 1,
+            };
+            if let Instruction::FunctionEnd = instruction {
+                break;
+            }
+        }
+        Ok(clocks)
+    }
+
+    /// Count of instruction words (including implicit returns)
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Count of RAM bytes used by the global variables and memory symbols referenced from the
+    /// assembly
+    pub fn ram_bytes(&self) -> u16 {
+        self.ram_bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(body: &str) -> Result<Program, Error> {
+        Program::from_c_source(&format!(
+            "uint8_t first[2];\nuint8_t second;\n\nvoid test(void) {{\n    __asm\n{}\n    __endasm;\n}}\n",
+            body,
+        ))
+    }
+
+    #[test]
+    fn split_offset_keeps_sign() {
+        assert_eq!(split_offset("_second+1").unwrap(), ("_second", 1));
+        assert_eq!(split_offset("_second-1").unwrap(), ("_second", -1));
+        assert_eq!(split_offset("_second").unwrap(), ("_second", 0));
+    }
+
+    #[test]
+    fn negative_offset_refers_to_preceding_symbol() {
+        let program = assemble("    mov a, _second-1").unwrap();
+        let first = program.symbol("_first").unwrap();
+        assert_eq!(program.symbol("_second").unwrap(), first + 2);
+        assert!(matches!(
+            program.instructions[0],
+            Instruction::Mov(Loc::A, Value::Loc(Loc::Mem(address))) if address == first + 1
+        ));
+    }
+
+    #[test]
+    fn address_out_of_ram_is_rejected() {
+        assert!(matches!(assemble("    mov a, _first-1"), Err(Error::Parse(_))));
+        assert!(matches!(assemble("    mov a, _second+300"), Err(Error::Parse(_))));
+    }
+}
@@ -0,0 +1,146 @@
+//! Search for the frequency and baud rate combinations a UART could be generated for
+//!
+//! Each combination is checked by building the generator, so the same limits apply as for the
+//! real code: clocks per bit, rounding deviation and the timing error budget.
+
+use crate::{
+    device::{ClockSource, Device},
+    mcu::Frequency,
+    uart::{self, UartGenerator},
+};
+
+pub const STANDARD_BAUD_RATES: &[u32] = &[
+    300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 28800, 38400, 57600, 76800, 115200, 230400,
+    250000, 460800, 500000, 921600, 1000000,
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SuggestOptions {
+    pub fractional_timing: bool,
+    /// Percents, see `UartGeneratorBuilder::max_deviation`
+    pub max_deviation: Option<f64>,
+    /// Percents, see `UartGeneratorBuilder::clock_tolerance`
+    pub clock_tolerance: Option<f64>,
+}
+
+/// Timings of the achievable combination
+#[derive(Clone, Copy, Debug)]
+pub struct Timing {
+    pub clocks_per_bit: u32,
+    pub deviation_percent: f64,
+}
+
+#[derive(Debug)]
+pub struct Candidate {
+    /// System clock setting, e.g. `IHRC/2`; `None` for frequencies the device clocks don't give
+    pub clock: Option<String>,
+    pub frequency: Frequency,
+    pub baud: u32,
+    pub timing: Result<Timing, uart::Error>,
+}
+
+fn check(frequency: Frequency, baud: u32, options: &SuggestOptions) -> Result<Timing, uart::Error> {
+    // Pins are not relevant for timings, but RX adds its sampling offset to the error budget
+    let mut builder = UartGenerator::builder()
+        .frequency(frequency)
+        .baud(baud)
+        .tx_port("A".parse().unwrap())
+        .tx_pin("3".parse().unwrap())
+        .rx_port("A".parse().unwrap())
+        .rx_pin("4".parse().unwrap());
+    if options.fractional_timing {
+        builder = builder.fractional_timing();
+    }
+    if let Some(max_deviation) = options.max_deviation {
+        builder = builder.max_deviation(max_deviation);
+    }
+    if let Some(clock_tolerance) = options.clock_tolerance {
+        builder = builder.clock_tolerance(clock_tolerance);
+    }
+    let generator = builder.build()?;
+    Ok(Timing {
+        clocks_per_bit: generator.clocks_per_bit(),
+        deviation_percent: generator.clock_deviation() * 100f64,
+    })
+}
+
+fn clock_sources(device: Option<Device>) -> &'static [ClockSource] {
+    match device {
+        Some(device) => device.clock_sources(),
+        None => Device::common_clock_sources(),
+    }
+}
+
+/// Standard baud rates for the given system clock frequency
+pub fn for_frequency(
+    frequency: Frequency,
+    device: Option<Device>,
+    options: &SuggestOptions,
+) -> Vec<Candidate> {
+    let clock = clock_sources(device)
+        .iter()
+        .flat_map(ClockSource::options)
+        .find(|(_, hz)| *hz == frequency.hz())
+        .map(|(name, _)| name);
+    STANDARD_BAUD_RATES
+        .iter()
+        .map(|baud| Candidate {
+            clock: clock.clone(),
+            frequency,
+            baud: *baud,
+            timing: check(frequency, *baud, options),
+        })
+        .collect()
+}
+
+/// System clock options of the device for the given baud rate
+pub fn for_baud(baud: u32, device: Option<Device>, options: &SuggestOptions) -> Vec<Candidate> {
+    clock_sources(device)
+        .iter()
+        .flat_map(ClockSource::options)
+        .map(|(name, hz)| {
+            let frequency = Frequency::from_hz(hz);
+            Candidate {
+                clock: Some(name),
+                frequency,
+                baud,
+                timing: check(frequency, baud, options),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_baud_rates_are_checked_for_frequency() {
+        let candidates = for_frequency("1mhz".parse().unwrap(), None, &SuggestOptions::default());
+        assert_eq!(candidates.len(), STANDARD_BAUD_RATES.len());
+        let candidate = |baud| candidates.iter().find(|candidate| candidate.baud == baud).unwrap();
+        assert_eq!(candidate(9600).clock.as_deref(), Some("IHRC/16"));
+        assert_eq!(candidate(9600).timing.as_ref().unwrap().clocks_per_bit, 104);
+        assert!(matches!(candidate(57600).timing, Err(uart::Error::TooBigClockDerivation(_))));
+        assert!(matches!(candidate(115200).timing, Err(uart::Error::VeryFewClocksPerBit(9))));
+
+        // Crystal-like frequency is not one of the IHRC/ILRC options
+        let candidates = for_frequency(Frequency::from_hz(7_372_800), None, &SuggestOptions::default());
+        assert!(candidates.iter().all(|candidate| candidate.clock.is_none()));
+    }
+
+    #[test]
+    fn device_clock_options_are_checked_for_baud() {
+        let accepted = |options: &SuggestOptions| -> Vec<String> {
+            for_baud(115200, Some("PFS154".parse().unwrap()), options)
+                .into_iter()
+                .filter(|candidate| candidate.timing.is_ok())
+                .filter_map(|candidate| candidate.clock)
+                .collect()
+        };
+        assert_eq!(accepted(&SuggestOptions::default()), ["IHRC/2", "IHRC/4"]);
+        // Rounding deviation of both is above 0.5%
+        let options = SuggestOptions { max_deviation: Some(0.5), ..SuggestOptions::default() };
+        assert!(accepted(&options).is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Hardware timer settings for a periodic interrupt
+//!
+//! TM2/TM3 count up to the bound register in the period mode and restart by themselves, so their
+//! period is exact. T16 has no bound register: it interrupts when bit 15 of the counter rises, and
+//! the ISR moves the counter back by the period.
+
+use serde::Serialize;
+
+use crate::mcu::Timer;
+
+/// Clock prescaler values, encoded by their index
+const PRESCALERS: [u32; 4] = [1, 4, 16, 64];
+/// TM2/TM3 scaler divides by 1..=32 after the prescaler
+const MAX_SCALER: u32 = 32;
+/// TM2/TM3 bound register is 8-bit, the period is `bound + 1` counts
+const MAX_TM_COUNTS: u32 = 256;
+/// T16 counts from the reload value up to 0x8000
+const MAX_T16_COUNTS: u32 = 0x8000;
+
+/// Timer settings which give the period closest to the requested one
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct TimerSetup {
+    pub timer: Timer,
+    pub prescaler: u32,
+    /// TM2/TM3 scaler; Always 1 for T16
+    pub scaler: u32,
+    /// Timer counts per period
+    pub counts: u32,
+    /// Interrupt period, system clocks
+    pub period_clocks: u32,
+}
+
+impl TimerSetup {
+    /// `None` when the period is out of the timer range
+    pub fn new(timer: Timer, period_clocks: f64) -> Option<Self> {
+        let (max_scaler, max_counts) = match timer {
+            Timer::T16 => (1, MAX_T16_COUNTS),
+            Timer::Tm2 | Timer::Tm3 => (MAX_SCALER, MAX_TM_COUNTS),
+        };
+        let mut best: Option<Self> = None;
+        for prescaler in PRESCALERS.iter().copied() {
+            for scaler in 1..=max_scaler {
+                let divider = prescaler * scaler;
+                let counts = (period_clocks / divider as f64).round() as u32;
+                if counts == 0 || counts > max_counts {
+                    continue;
+                }
+                let setup = Self {
+                    timer,
+                    prescaler,
+                    scaler,
+                    counts,
+                    period_clocks: divider * counts,
+                };
+                // Smaller dividers are tried first and kept on ties, they give finer steps
+                let error = (setup.period_clocks as f64 - period_clocks).abs();
+                if best.is_none_or(|best| error < (best.period_clocks as f64 - period_clocks).abs()) {
+                    best = Some(setup);
+                }
+            }
+        }
+        best
+    }
+
+    fn prescaler_code(&self) -> u8 {
+        PRESCALERS.iter().position(|prescaler| *prescaler == self.prescaler).unwrap() as u8
+    }
+
+    /// TM2S/TM3S: prescaler in bits 6:5, scaler - 1 in bits 4:0
+    pub fn tm_scaler_register(&self) -> u8 {
+        (self.prescaler_code() << 5) | (self.scaler - 1) as u8
+    }
+
+    /// TM2B/TM3B: counter restarts after reaching the bound
+    pub fn tm_bound_register(&self) -> u8 {
+        (self.counts - 1) as u8
+    }
+
+    /// T16M: system clock source, prescaler in bits 4:3, interrupt on bit 15
+    pub fn t16_mode_register(&self) -> u8 {
+        0x20 | (self.prescaler_code() << 3) | 0x07
+    }
+
+    /// Counter value stored by the ISR `latency_clocks` after the interrupt; The counter has
+    /// advanced past 0x8000 by then, so the latency is subtracted from the period
+    pub fn t16_reload(&self, latency_clocks: u32) -> u16 {
+        (MAX_T16_COUNTS - self.counts + latency_clocks / self.prescaler) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tm_registers_encode_smallest_exact_divider() {
+        let setup = TimerSetup::new(Timer::Tm2, 139.0).unwrap();
+        assert_eq!((setup.prescaler, setup.scaler, setup.counts), (1, 1, 139));
+        assert_eq!((setup.tm_scaler_register(), setup.tm_bound_register()), (0x00, 138));
+
+        let setup = TimerSetup::new(Timer::Tm3, 2500.0).unwrap();
+        assert_eq!((setup.prescaler, setup.scaler, setup.counts), (1, 10, 250));
+        assert_eq!((setup.tm_scaler_register(), setup.tm_bound_register()), (0x09, 249));
+
+        let setup = TimerSetup::new(Timer::Tm2, 100_000.0).unwrap();
+        assert_eq!((setup.prescaler, setup.scaler, setup.period_clocks), (16, 25, 100_000));
+        assert_eq!((setup.tm_scaler_register(), setup.tm_bound_register()), (0x58, 249));
+    }
+
+    #[test]
+    fn t16_registers_encode_prescaler_and_reload() {
+        let setup = TimerSetup::new(Timer::T16, 139.0).unwrap();
+        assert_eq!((setup.prescaler, setup.scaler, setup.counts), (1, 1, 139));
+        assert_eq!(setup.t16_mode_register(), 0x27);
+        assert_eq!(setup.t16_reload(12), 0x8000 - 139 + 12);
+
+        let setup = TimerSetup::new(Timer::T16, 40_000.0).unwrap();
+        assert_eq!((setup.prescaler, setup.counts), (4, 10_000));
+        assert_eq!(setup.t16_mode_register(), 0x2F);
+        assert_eq!(setup.t16_reload(12), 0x8000 - 10_000 + 3);
+    }
+
+    #[test]
+    fn period_out_of_range_is_rejected() {
+        assert!(TimerSetup::new(Timer::Tm2, 64.0 * 32.0 * 256.0).is_some());
+        assert!(TimerSetup::new(Timer::Tm2, 64.0 * 32.0 * 257.0).is_none());
+        assert!(TimerSetup::new(Timer::T16, 64.0 * 40_000.0).is_none());
+        assert!(TimerSetup::new(Timer::T16, 0.4).is_none());
+    }
+}
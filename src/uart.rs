@@ -1,34 +1,155 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}};
+
 use thiserror::Error;
-use log::info;
-use serde::Serialize;
+use log::{info, warn};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 use tinytemplate::TinyTemplate;
 
 use crate::{
-    mcu::{Frequency, Port, Pin},
-    config::{AppConfig, AppSubcommand},
+    mcu::{BaudRates, Frequency, Port, Pin, Timer},
+    device::{self, Device},
+    pins::{self, PinAllocator, PinClaim, PinUsage},
+    delay::{Delay, DelayLoop},
+    peripheral::{
+        self, GeneratedFiles, GeneratedPeripheral, McuConfig, OutputOptions, PeripheralConfig,
+        PeripheralGenerator, Resources,
+    },
+    sim::{self, Program},
+    timer::TimerSetup,
 };
-use crate::mcu::StopBits;
-
-const DEFAULT_MAX_CLOCK_DERIVATION: f64 = 0.01;
-const MAX_CLOCKS_PER_BIT: u32 = 256 * 4;
+use crate::mcu::{StopBits, DataBits, Parity, SyncChar};
+
+const DEFAULT_MAX_DEVIATION_PERCENT: f64 = 1.0;
+/// Factory calibrated IHRC is within about 1% at the nominal voltage and temperature
+const DEFAULT_CLOCK_TOLERANCE_PERCENT: f64 = 1.0;
+/// Start bit is detected by `t0sn` + `goto .-1` polling loop, so the edge is seen up to 3T late
+const RX_START_DETECTION_CLOCKS: u32 = 3;
+/// Polled start bit reaches the early check of `uart{N}_receive` 8T later: `t0sn` skip and `ret`
+/// of `uart{N}_rx_wait_ready`, then the caller takes 4T to call the receive function;
+/// `uart{N}_receive_timeout` jumps there right after its `t1sn` poll
+const RX_WAIT_READY_RETURN_CLOCKS: u32 = 8;
+const RX_TIMEOUT_ENTRY_CLOCKS: u32 = 3;
+/// Stop bit validation and the return after its sample (8 data bits; 9 data bits take 3T less
+/// without parity, shorter words take 1T more per alignment shift)
+const RX_STOP_BIT_RETURN_CLOCKS: u32 = 10;
+/// Caller takes the result and polls for the next start bit, as on the TX side
+const RX_NEXT_CALL_CLOCKS: u32 = 4;
+/// `uart{N}_rx_isr` calls the receive code through the `uart{N}_receive_isr` jump
+const RX_ISR_ENTRY_CLOCKS: u32 = 4;
+/// `uart{N}_rx_isr` stores the word after the receive code returns (9 data bits take 5T more)
+const RX_ISR_STORE_CLOCKS: u32 = 21;
+/// `uart{N}_receive_timeout` polls the start bit each 5T: `t1sn`, then `dzsn` + `goto` of the
+/// poll counter or the tick counter update
+const RX_TIMEOUT_POLL_CLOCKS: u32 = 5;
+/// Polls per tick: up to 255 of the poll counter and one of the tick counter update
+const RX_TIMEOUT_MAX_POLLS: u32 = 256;
+/// Runtime bit wait takes `8 * loops + rest + 19` clocks: the call, the setup, the 8T loops, the
+/// rest of 0 to 7 clocks and the return
+const RUNTIME_WAIT_LOOP_CLOCKS: u32 = 8;
+const RUNTIME_WAIT_OVERHEAD_CLOCKS: u32 = 19;
+/// Loops of the runtime bit wait are counted in a byte
+const RUNTIME_MAX_WAIT_CLOCKS: u32 = 255 * RUNTIME_WAIT_LOOP_CLOCKS + 7;
+/// Autobaud polls the start bit edge each 3T and the last falling edge each 8T, 8 bits later; The
+/// measured bit period is within this error
+const AUTOBAUD_MEASUREMENT_ERROR_CLOCKS: f64 = 0.75;
+/// Version of the `TemplateContext` fields set; Bumped on any incompatible change
+pub const TEMPLATE_VERSION: u32 = 4;
+/// First line of the custom template, followed by the version it was written for
+const TEMPLATE_HEADER: &str = "// freepdk-gen uart template v";
 const MIN_CLOCKS_PER_BIT: u32 = 16;
+/// External interrupt pins, which could detect the start bit edge; Index is the bit of the pin in
+/// `INTEN` and `INTRQ`
+pub const RX_INTERRUPT_PINS: &[(char, u8)] = &[('A', 0), ('B', 0)];
+/// Clocks from the interrupt request to the generated ISR function (e.g. `uart{N}_rx_isr`) code:
+/// interrupt response, SDCC interrupt prologue and the call from the application handler
+pub const ISR_LATENCY_CLOCKS: u32 = 12;
+/// Return from the ISR function, SDCC interrupt epilogue and `reti`
+const ISR_EXIT_CLOCKS: u32 = 8;
+/// Timer-driven RX looks for the start bit on each tick, so its edge is found within a third of a
+/// bit and the samples are taken close to the middle of the bits
+const TIMER_RX_TICKS_PER_BIT: u32 = 3;
+/// Timer ISR samples RX after the TX part, which takes 3 to 16 clocks
+const TIMER_RX_SAMPLE_JITTER_CLOCKS: u32 = 13;
+/// Buffered TX flags the idle line, which moves the RX samples by 2 more clocks
+const TIMER_TX_BUFFER_JITTER_CLOCKS: u32 = 2;
+/// Timer-driven TX counts frame bits in a byte
+const TIMER_MAX_TX_FRAME_BITS: u32 = 255;
+const DEFAULT_RX_BUFFER_SIZE: u8 = 8;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Invalid generator options")]
-    InvalidOptions,
-    #[error("{} clocks per bit is not implemented yet. Max allowed clocks per bit are {}, try lower frequency or higher baud rate", _0, MAX_CLOCKS_PER_BIT)]
-    TooManyClocksPerBit(u32),
-    #[error("{} clocks per stop bit is not implemented yet. Max allowed clocks per bit are {}, try lower frequency or higher baud rate", _0, MAX_CLOCKS_PER_BIT)]
-    TooManyClocksPerStopBit(u32),
+    #[error("MCU frequency is not specified")]
+    MissingFrequency,
+    #[error("UART baud rate is not specified")]
+    MissingBaud,
     #[error("Clock derivation is higher than allowed {:.2}%", _0 * 100f64)]
     TooBigClockDerivation(f64),
+    #[error("Timing error budget {:.2}% exceeds {:.2}% tolerated by the receiver for this frame length; Try another frequency or baud rate", _0.total_percent, _0.allowed_percent)]
+    ErrorBudgetExceeded(ErrorBudget),
     #[error("Calculated clocks count per bit ({}) is too small (more than {} is required), try higher frequency or lower baud rate", _0, MIN_CLOCKS_PER_BIT)]
     VeryFewClocksPerBit(u32),
     #[error("Calculated clocks count per half bit ({}) is too small (more than {} is required), try higher frequency or lower baud rate", _0, MIN_CLOCKS_PER_BIT)]
     VeryFewClocksPerHalfBit(u32),
+    #[error("Both port and pin should be specified for UART {}", _0)]
+    IncompletePin(&'static str),
+    #[error("At least one of UART TX or RX pins should be specified")]
+    NoPinsSpecified,
+    #[error("Half-duplex UART uses a single pin, TX and RX pins should be the same")]
+    HalfDuplexPinMismatch,
+    #[error("Half-duplex UART line can't be inverted, idle line is released to high level")]
+    HalfDuplexInverted,
+    #[error("Echo check is available only in half-duplex mode")]
+    EchoCheckWithoutHalfDuplex,
+    #[error("Interrupt-driven RX requires RX pin on an external interrupt pin: PA0 or PB0")]
+    RxInterruptPin,
+    #[error("Interrupt-driven RX can't be used in half-duplex mode, own frames would trigger it")]
+    RxInterruptHalfDuplex,
+    #[error("RX buffer size ({}) should be a power of two from 2 to 128", _0)]
+    InvalidRxBufferSize(u8),
+    #[error("Calculated clocks count per half bit ({}) is too small for interrupt-driven RX (at least {} is required), try higher frequency or lower baud rate", _0, MIN_CLOCKS_PER_BIT + ISR_LATENCY_CLOCKS)]
+    VeryFewClocksForRxInterrupt(u32),
+    #[error("Timer-driven UART doesn't support {}", _0)]
+    TimerUnsupported(&'static str),
+    #[error("Bit period is out of the {} range, try another timer, frequency or baud rate", _0)]
+    TimerPeriodOutOfRange(Timer),
+    #[error("TX buffer is sent from the timer interrupt, it requires the timer-driven UART")]
+    TxBufferWithoutTimer,
+    #[error("TX buffer size ({}) should be a power of two from 2 to 128", _0)]
+    InvalidTxBufferSize(u8),
+    #[error("Autobaud measures the bit period on RX, RX pin should be specified")]
+    AutobaudWithoutRx,
+    #[error("Autobaud doesn't support {}", _0)]
+    AutobaudUnsupported(&'static str),
+    #[error("Initial baud rate {} is out of the autobaud range {}..{} for this frequency", _0, _1, _2)]
+    AutobaudBaudOutOfRange(u32, u32, u32),
+    #[error("Baud rate table doesn't support {}", _0)]
+    BaudTableUnsupported(&'static str),
+    #[error("Initial baud rate {} should be listed in the baud rate table", _0)]
+    InitialBaudNotInTable(u32),
+    #[error("Baud rate list already defines the baud rate table, `baud_table` should be omitted")]
+    BaudTableDefinedTwice,
+    #[error("Baud rate {} is listed in the baud rate table twice", _0)]
+    DuplicateBaudTableEntry(u32),
+    #[error("Baud rate {} of the table: {}", _0, _1)]
+    BaudTableEntry(u32, Box<Error>),
+    #[error("Baud rate {} of the table is out of the runtime wait range {}..{} for this frequency", _0, _1, _2)]
+    BaudTableOutOfRange(u32, u32, u32),
+    #[error("Timer ISR takes up to {} clocks, which doesn't fit into the timer period of {} clocks; Try higher frequency or lower baud rate", _0, _1)]
+    TimerIsrTooLong(u32, u32),
+    #[error(transparent)]
+    Pins(#[from] pins::Error),
+    #[error(transparent)]
+    Device(#[from] device::Error),
+    #[error("Custom template should start with the `{}{}` line; Use `dump-template` to get the built-in template", TEMPLATE_HEADER, TEMPLATE_VERSION)]
+    TemplateHeaderMissing,
+    #[error("Custom template is written for template version {} but version {} is required; Update it from the `dump-template` output", _0, TEMPLATE_VERSION)]
+    TemplateVersionMismatch(u32),
+    #[error("Custom template produces a single file and can't be used with the split output")]
+    CustomTemplateSplit,
     #[error("Template rendering failed: {}", _0)]
     TemplateFailure(String),
+    #[error(transparent)]
+    Assembly(#[from] sim::Error),
 }
 
 impl From<tinytemplate::error::Error> for Error {
@@ -37,129 +158,429 @@ impl From<tinytemplate::error::Error> for Error {
     }
 }
 
+/// UART options, independent of the command line interface; Could be (de)serialized, so other
+/// tools could embed them into their own configs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UartConfig {
+    pub frequency: Option<Frequency>,
+    pub device: Option<Device>,
+    pub baud: Option<u32>,
+    pub tx_port: Option<Port>,
+    pub tx_pin: Option<Pin>,
+    pub invert_tx: bool,
+    pub rx_port: Option<Port>,
+    pub rx_pin: Option<Pin>,
+    pub invert_rx: bool,
+    /// TX and RX share a single open-drain line
+    pub half_duplex: bool,
+    /// Read back the line while sending to detect collisions (half-duplex only)
+    pub echo_check: bool,
+    pub uart_num: u8,
+    pub stop_bits: StopBits,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    /// Extra idle line time after each sent frame, bits
+    pub idle_bits: u32,
+    /// Bits take individual clock counts, so the rounding error doesn't accumulate over the frame
+    pub fractional_timing: bool,
+    /// Max baud rate deviation due to the rounding of clocks per bit, percents
+    pub max_deviation: Option<f64>,
+    /// MCU clock tolerance counted in the timing error budget, percents
+    pub clock_tolerance: Option<f64>,
+    /// Receive from the start bit interrupt into a ring buffer
+    pub rx_interrupt: bool,
+    /// Ring buffer size of the interrupt-driven RX, power of two
+    pub rx_buffer_size: Option<u8>,
+    /// Clock the bits from the timer interrupt instead of the busy loops
+    pub timer: Option<Timer>,
+    /// Ring buffer size of the timer-driven TX, power of two; Adds non-blocking writes
+    pub tx_buffer_size: Option<u8>,
+    /// Generate `uart{N}_receive_timeout`, which gives up waiting for the start bit
+    pub rx_timeout: bool,
+    /// Generate `uart{N}_autobaud`, which measures the bit period on this sync character; `baud`
+    /// is the initial baud rate then
+    pub autobaud: Option<SyncChar>,
+    /// Baud rates selected at runtime by `uart{N}_set_baud(index)`; `baud` is the initial one and
+    /// should be listed as well
+    pub baud_table: Vec<u32>,
+}
+
+/// UART instance: generator options plus where its code goes; `[[uart]]` entry of the project file
+#[derive(Deserialize)]
+pub struct UartOptions {
+    /// Initial baud rate, a list adds the `uart<N>_set_baud` table like `--baud` does
+    pub baud: Option<BaudRates>,
+    /// Custom template of the single-file output
+    pub template: Option<PathBuf>,
+    #[serde(flatten)]
+    pub config: UartConfig,
+    #[serde(flatten)]
+    pub output: OutputOptions,
+    /// Fields left after the flattened ones; `deny_unknown_fields` doesn't work with flattening
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+
+impl UartOptions {
+    pub fn new(config: UartConfig, output: OutputOptions) -> Self {
+        Self { baud: None, template: None, config, output, unknown: BTreeMap::new() }
+    }
+
+    /// Fields which are not UART options; MCU settings are taken from the project instead
+    pub fn unknown_fields(&self) -> impl Iterator<Item = &str> {
+        let frequency = self.config.frequency.map(|_| "frequency");
+        let device = self.config.device.map(|_| "device");
+        frequency.into_iter().chain(device).chain(self.unknown.keys().map(String::as_str))
+    }
+}
+
 #[derive(Default)]
 pub struct UartGeneratorBuilder {
-    frequency: Option<Frequency>,
-    baud: Option<u32>,
-    tx_pin: Option<Pin>,
-    tx_port: Option<Port>,
-    invert_tx: bool,
-    rx_port: Option<Port>,
-    rx_pin: Option<Pin>,
-    invert_rx: bool,
-    max_clock_derivation: Option<f64>,
-    uart_num: Option<u8>,
-    stop_bits: Option<StopBits>,
+    config: UartConfig,
+    template: Option<String>,
 }
 
 impl UartGeneratorBuilder {
-    pub fn load_config(mut self, config: &AppConfig) -> Result<Self, Error> {
-        #[allow(unreachable_patterns)]
-        let uart = match &config.subcommand {
-            AppSubcommand::Uart(command) => command,
-            _ => panic!("UartGenerator::from_config should called only when uart subcommand is active"),
-        };
-
-        self.frequency.replace(config.freq);
-        self.baud.replace(uart.baud);
-        self.tx_port.replace(uart.tx_port);
-        self.tx_pin.replace(uart.tx_pin);
-        self.invert_tx = uart.invert_tx;
-        self.uart_num.replace(uart.uart_num);
-        self.stop_bits.replace(uart.stop_bits);
-        self.rx_port.replace(uart.rx_port);
-        self.rx_pin.replace(uart.rx_pin);
-        self.invert_rx = uart.invert_rx;
-        Ok(self)
+    /// Replaces all options with the given config
+    pub fn config(mut self, config: UartConfig) -> Self {
+        self.config = config;
+        self
     }
 
     pub fn frequency(mut self, frequency: Frequency) -> Self {
-        self.frequency.replace(frequency);
+        self.config.frequency.replace(frequency);
+        self
+    }
+
+    pub fn device(mut self, device: Device) -> Self {
+        self.config.device.replace(device);
         self
     }
 
     pub fn baud(mut self, baud: u32) -> Self {
-        self.baud.replace(baud);
+        self.config.baud.replace(baud);
         self
     }
 
     pub fn tx_port(mut self, tx_port: Port) -> Self {
-        self.tx_port.replace(tx_port);
+        self.config.tx_port.replace(tx_port);
         self
     }
 
     pub fn tx_pin(mut self, tx_pin: Pin) -> Self {
-        self.tx_pin.replace(tx_pin);
+        self.config.tx_pin.replace(tx_pin);
         self
     }
 
     pub fn invert_tx(mut self) -> Self {
-        self.invert_tx = true;
+        self.config.invert_tx = true;
         self
     }
 
     pub fn rx_port(mut self, rx_port: Port) -> Self {
-        self.rx_port.replace(rx_port);
+        self.config.rx_port.replace(rx_port);
         self
     }
 
     pub fn rx_pin(mut self, rx_pin: Pin) -> Self {
-        self.rx_pin.replace(rx_pin);
+        self.config.rx_pin.replace(rx_pin);
         self
     }
 
     pub fn invert_rx(mut self) -> Self {
-        self.invert_rx = true;
+        self.config.invert_rx = true;
+        self
+    }
+
+    /// TX and RX share a single open-drain line
+    pub fn half_duplex(mut self) -> Self {
+        self.config.half_duplex = true;
+        self
+    }
+
+    /// Read back the line while sending to detect collisions (half-duplex only)
+    pub fn echo_check(mut self) -> Self {
+        self.config.echo_check = true;
         self
     }
 
     pub fn uart_num(mut self, num: u8) -> Self {
-        self.uart_num.replace(num);
+        self.config.uart_num = num;
         self
     }
 
     pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
-        self.stop_bits.replace(stop_bits);
+        self.config.stop_bits = stop_bits;
+        self
+    }
+
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.config.data_bits = data_bits;
+        self
+    }
+
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.config.parity = parity;
+        self
+    }
+
+    pub fn idle_bits(mut self, idle_bits: u32) -> Self {
+        self.config.idle_bits = idle_bits;
+        self
+    }
+
+    /// Bits take individual clock counts, so the rounding error doesn't accumulate over the frame
+    pub fn fractional_timing(mut self) -> Self {
+        self.config.fractional_timing = true;
+        self
+    }
+
+    /// Max baud rate deviation due to the rounding of clocks per bit, percents
+    pub fn max_deviation(mut self, percent: f64) -> Self {
+        self.config.max_deviation.replace(percent);
+        self
+    }
+
+    /// MCU clock tolerance counted in the timing error budget, percents
+    pub fn clock_tolerance(mut self, percent: f64) -> Self {
+        self.config.clock_tolerance.replace(percent);
+        self
+    }
+
+    /// Receive from the start bit interrupt into a ring buffer
+    pub fn rx_interrupt(mut self) -> Self {
+        self.config.rx_interrupt = true;
+        self
+    }
+
+    /// Ring buffer size of the interrupt-driven RX, power of two
+    pub fn rx_buffer_size(mut self, size: u8) -> Self {
+        self.config.rx_buffer_size.replace(size);
+        self
+    }
+
+    /// Clock the bits from the timer interrupt instead of the busy loops
+    pub fn timer(mut self, timer: Timer) -> Self {
+        self.config.timer.replace(timer);
+        self
+    }
+
+    /// Ring buffer size of the timer-driven TX, power of two
+    pub fn tx_buffer_size(mut self, size: u8) -> Self {
+        self.config.tx_buffer_size.replace(size);
+        self
+    }
+
+    /// Generate `uart{N}_receive_timeout`, which gives up waiting for the start bit
+    pub fn rx_timeout(mut self) -> Self {
+        self.config.rx_timeout = true;
+        self
+    }
+
+    /// Generate `uart{N}_autobaud`, which measures the bit period on the sync character
+    pub fn autobaud(mut self, sync: SyncChar) -> Self {
+        self.config.autobaud.replace(sync);
+        self
+    }
+
+    /// Baud rates selected at runtime by `uart{N}_set_baud(index)`, see `UartConfig::baud_table`
+    pub fn baud_table(mut self, baud_table: Vec<u32>) -> Self {
+        self.config.baud_table = baud_table;
         self
     }
 
-    fn validate_all_params_specified(&self) -> Result<(), Error> {
-        self.frequency.ok_or(Error::InvalidOptions)?;
-        self.baud.ok_or(Error::InvalidOptions)?;
-        Ok(())
+    /// Custom template of the single-file output, see `builtin_template`
+    pub fn template(mut self, template: String) -> Self {
+        self.template.replace(template);
+        self
     }
 
     pub fn build(self) -> Result<UartGenerator, Error> {
-        self.validate_all_params_specified()?;
-
-        let frequency = self.frequency.expect("Frequency should be specified");
-        let baud = self.baud.expect("Baud rate should be specified");
-        let tx_port = self.tx_port.expect("Tx port should be specified");
-        let tx_pin = self.tx_pin.expect("Tx pin should be specified");
-        let max_clock_rate_derivation = self.max_clock_derivation
-            .unwrap_or(DEFAULT_MAX_CLOCK_DERIVATION);
-        let uart_num = self.uart_num.expect("Uart number should be specified");
-        let invert_tx = self.invert_tx;
-        let rx_port = self.rx_port.expect("Rx port should be specified");
-        let rx_pin = self.rx_pin.expect("Rx pin should be specified");
-        let invert_rx = self.invert_rx;
+        let config = self.config;
+        let max_clock_rate_derivation = config.max_deviation
+            .unwrap_or(DEFAULT_MAX_DEVIATION_PERCENT) / 100f64;
+        let template = self.template.as_deref().map(parse_template).transpose()?;
+
+        let frequency = config.frequency.ok_or(Error::MissingFrequency)?;
+        let baud = config.baud.ok_or(Error::MissingBaud)?;
+        let tx = match (config.tx_port, config.tx_pin) {
+            (Some(port), Some(pin)) => Some((port, pin)),
+            (None, None) => None,
+            _ => return Err(Error::IncompletePin("TX")),
+        };
+        let uart_num = config.uart_num;
+        let invert_tx = config.invert_tx;
+        let rx = match (config.rx_port, config.rx_pin) {
+            (Some(port), Some(pin)) => Some((port, pin)),
+            (None, None) => None,
+            _ => return Err(Error::IncompletePin("RX")),
+        };
+
+        // Single line is used for both directions, it could be specified as either of them
+        let (tx, rx) = if config.half_duplex {
+            let line = match (tx, rx) {
+                (Some(tx), Some(rx)) if tx != rx => return Err(Error::HalfDuplexPinMismatch),
+                (tx, rx) => tx.or(rx),
+            };
+            if config.invert_tx || config.invert_rx {
+                return Err(Error::HalfDuplexInverted);
+            }
+            (line, line)
+        } else {
+            if config.echo_check {
+                return Err(Error::EchoCheckWithoutHalfDuplex);
+            }
+            (tx, rx)
+        };
+        if tx.is_none() && rx.is_none() {
+            return Err(Error::NoPinsSpecified);
+        }
+        let rx_buffer_size = config.rx_buffer_size.unwrap_or(DEFAULT_RX_BUFFER_SIZE);
+        if config.rx_interrupt {
+            if config.half_duplex {
+                return Err(Error::RxInterruptHalfDuplex);
+            }
+            if !matches!(rx, Some((port, pin)) if RX_INTERRUPT_PINS.contains(&(port.char(), pin.num()))) {
+                return Err(Error::RxInterruptPin);
+            }
+            if !(2..=128).contains(&rx_buffer_size) || !rx_buffer_size.is_power_of_two() {
+                return Err(Error::InvalidRxBufferSize(rx_buffer_size));
+            }
+        }
+        let tx_buffer_size = tx.and(config.tx_buffer_size);
+        if let Some(size) = tx_buffer_size {
+            if config.timer.is_none() {
+                return Err(Error::TxBufferWithoutTimer);
+            }
+            if !(2..=128).contains(&size) || !size.is_power_of_two() {
+                return Err(Error::InvalidTxBufferSize(size));
+            }
+        }
+        let rx_timeout = rx.is_some() && config.rx_timeout;
+        let invert_rx = config.invert_rx;
+        let data_bits = config.data_bits;
+        let parity = config.parity;
+
+        if config.timer.is_some() {
+            let unsupported = [
+                (config.half_duplex, "half-duplex mode"),
+                (config.echo_check, "echo check"),
+                (config.fractional_timing, "fractional timing, the timer period is used instead"),
+                (config.rx_interrupt, "interrupt-driven RX, it receives in the background already"),
+                (config.rx_timeout, "receive timeout, frames are received in the background"),
+                (config.autobaud.is_some(), "autobaud, bits are clocked by the timer period"),
+                (!config.baud_table.is_empty(), "baud rate table, the timer is set up for a single period"),
+                (config.stop_bits == StopBits::OneAndHalf, "1.5 stop bits"),
+                (template.is_some(), "custom templates"),
+            ];
+            if let Some((_, feature)) = unsupported.iter().find(|(used, _)| *used) {
+                return Err(Error::TimerUnsupported(feature));
+            }
+        }
+
+        // Autobaud and the baud rate table share the runtime bit waits
+        if config.autobaud.is_some() || !config.baud_table.is_empty() {
+            let unsupported = [
+                (config.echo_check, "echo check"),
+                (config.fractional_timing, "fractional timing, runtime waits take whole clocks"),
+                (config.rx_interrupt, "interrupt-driven RX"),
+                (config.stop_bits == StopBits::OneAndHalf, "1.5 stop bits"),
+                (template.is_some(), "custom templates"),
+            ];
+            if let Some((_, feature)) = unsupported.iter().find(|(used, _)| *used) {
+                return Err(match config.autobaud {
+                    Some(_) => Error::AutobaudUnsupported(feature),
+                    None => Error::BaudTableUnsupported(feature),
+                });
+            }
+        }
+        if config.autobaud.is_some() {
+            if rx.is_none() {
+                return Err(Error::AutobaudWithoutRx);
+            }
+            if data_bits.count() < 8 {
+                return Err(Error::AutobaudUnsupported("less than 8 data bits, the sync character doesn't fit"));
+            }
+        }
+
+        // Each table entry is validated as a single baud rate; Generated code is the same for all
+        // of them, only the waits differ
+        if !config.baud_table.is_empty() && !config.baud_table.contains(&baud) {
+            return Err(Error::InitialBaudNotInTable(baud));
+        }
+        let mut baud_table: Vec<BaudTableEntry> = Vec::with_capacity(config.baud_table.len());
+        for entry in config.baud_table.iter().copied() {
+            if baud_table.iter().any(|other| other.baud == entry) {
+                return Err(Error::DuplicateBaudTableEntry(entry));
+            }
+            let entry_config = UartConfig { baud: Some(entry), baud_table: Vec::new(), ..config.clone() };
+            let generator = UartGenerator::builder()
+                .config(entry_config)
+                .build()
+                .map_err(|e| Error::BaudTableEntry(entry, Box::new(e)))?;
+            baud_table.push(BaudTableEntry {
+                baud: entry,
+                clocks_per_bit: generator.clocks_per_bit,
+                clocks_per_half_bit: generator.clocks_per_half_bit,
+                deviation_percent: generator.clock_deviation * 100f64,
+            });
+        }
+
+        if config.device.is_none() {
+            warn!("Target device is not specified, pins availability is not checked");
+        }
 
         let expected_clocks_per_bit = (frequency.hz() as f64) / baud as f64;
-        let clocks_per_bit = expected_clocks_per_bit.round() as u32;
+
+        // Timer interrupt clocks the bits; RX takes several samples per bit to find the start bit
+        let ticks_per_bit = if rx.is_some() { TIMER_RX_TICKS_PER_BIT } else { 1 };
+        let timer = match config.timer {
+            Some(timer) => {
+                if let Some(device) = config.device {
+                    device.check_timer(timer)?;
+                }
+                let setup = TimerSetup::new(timer, expected_clocks_per_bit / ticks_per_bit as f64)
+                    .ok_or(Error::TimerPeriodOutOfRange(timer))?;
+                info!(
+                    "{} period: {} clocks (prescaler {}, scaler {}, {} counts)",
+                    timer, setup.period_clocks, setup.prescaler, setup.scaler, setup.counts
+                );
+                Some(setup)
+            }
+            None => None,
+        };
+        let clocks_per_bit = match timer {
+            Some(setup) => setup.period_clocks * ticks_per_bit,
+            None => expected_clocks_per_bit.round() as u32,
+        };
 
         info!("Estimated clocks per bit: {}", clocks_per_bit);
         let bit_period = clocks_per_bit as f64 / frequency.hz() as f64;
         info!("Bit period: {:.4}ms ({:.4}us)", bit_period * 1000f64, bit_period * 1000000f64);
 
-        if  clocks_per_bit > MAX_CLOCKS_PER_BIT {
-            return Err(Error::TooManyClocksPerBit(clocks_per_bit))
-        }
         if clocks_per_bit < MIN_CLOCKS_PER_BIT {
             return Err(Error::VeryFewClocksPerBit(clocks_per_bit))
         }
 
-        let clock_derivation = (clocks_per_bit as f64 - expected_clocks_per_bit as f64).abs()
-            / expected_clocks_per_bit;
+        // Start, data and parity bits; Stop bit begins right after them
+        let frame_bits = 1 + data_bits.count() as u32 + (parity != Parity::None) as u32;
+        let fractional_timing = if config.fractional_timing {
+            Some(FractionalTiming::new(expected_clocks_per_bit))
+        } else {
+            None
+        };
+
+        // With fractional timing the rounding error doesn't accumulate, only the error of the stop
+        // bit edge is left
+        let clock_derivation = match fractional_timing {
+            Some(timing) => {
+                let expected_offset = frame_bits as f64 * expected_clocks_per_bit;
+                (timing.offset(frame_bits) as f64 - expected_offset).abs() / expected_offset
+            }
+            None => (clocks_per_bit as f64 - expected_clocks_per_bit).abs()
+                / expected_clocks_per_bit,
+        };
 
         info!("Clock rate derivation due to rounding error: {:.2}%", clock_derivation * 100f64);
 
@@ -167,15 +588,22 @@ impl UartGeneratorBuilder {
             return Err(Error::TooBigClockDerivation(max_clock_rate_derivation));
         }
 
-        let clocks_per_stop_bit = match self.stop_bits.unwrap_or(StopBits::One) {
-            StopBits::One => clocks_per_bit,
-            StopBits::Two => (expected_clocks_per_bit * 2.0).round() as u32,
-            StopBits::OneAndHalf =>  (expected_clocks_per_bit * 1.5).round() as u32,
+        let stop_bits = match config.stop_bits {
+            StopBits::One => 1.0,
+            StopBits::Two => 2.0,
+            StopBits::OneAndHalf => 1.5,
+        };
+        let clocks_per_stop_bit = match fractional_timing {
+            // Stop bit ends as close to the ideal end of the frame as possible
+            Some(timing) => ((frame_bits as f64 + stop_bits) * expected_clocks_per_bit).round()
+                as u32 - timing.offset(frame_bits),
+            None if config.stop_bits == StopBits::One => clocks_per_bit,
+            // Timer clocks whole bits
+            None if timer.is_some() => clocks_per_bit * 2,
+            None => (expected_clocks_per_bit * stop_bits).round() as u32,
         };
 
-        if  clocks_per_stop_bit > MAX_CLOCKS_PER_BIT {
-            return Err(Error::TooManyClocksPerStopBit(clocks_per_stop_bit));
-        }
+        let idle_clocks = config.idle_bits * clocks_per_bit;
 
         let clocks_per_half_bit = (expected_clocks_per_bit * 0.5).round() as u32;
 
@@ -183,62 +611,375 @@ impl UartGeneratorBuilder {
             return Err(Error::VeryFewClocksPerHalfBit(clocks_per_bit));
         }
 
-        Ok(UartGenerator {
+        // ISR enters the receive code later than the polling, the start bit wait should cover that
+        if config.rx_interrupt && clocks_per_half_bit < MIN_CLOCKS_PER_BIT + ISR_LATENCY_CLOCKS {
+            return Err(Error::VeryFewClocksForRxInterrupt(clocks_per_half_bit));
+        }
+
+        // Back-to-back frames leave the receiver the rest of the stop bit and the idle time to
+        // return and poll for the next start bit (or leave the ISR before the next start bit
+        // interrupt), otherwise it falls behind with each frame. Single frames are still received,
+        // so it is only a warning. The timer variant receives in the background, its callers are
+        // not timed
+        let mut rx_turnaround = None;
+        if rx.is_some() && timer.is_none() {
+            let return_clocks = match data_bits.count() {
+                9 if parity == Parity::None => RX_STOP_BIT_RETURN_CLOCKS - 3,
+                count => RX_STOP_BIT_RETURN_CLOCKS + 8u32.saturating_sub(count as u32),
+            };
+            let turnaround = if config.rx_interrupt {
+                let store_clocks = RX_ISR_STORE_CLOCKS + if data_bits.count() > 8 { 5 } else { 0 };
+                ISR_LATENCY_CLOCKS + RX_ISR_ENTRY_CLOCKS + clocks_per_half_bit + return_clocks + store_clocks + ISR_EXIT_CLOCKS
+            } else {
+                let entry_clocks = if rx_timeout { RX_TIMEOUT_ENTRY_CLOCKS } else { RX_WAIT_READY_RETURN_CLOCKS };
+                entry_clocks + clocks_per_half_bit + return_clocks + RX_NEXT_CALL_CLOCKS
+            };
+            let frame_tail = clocks_per_stop_bit + idle_clocks;
+            if turnaround > frame_tail {
+                let suggested_idle_bits = config.idle_bits + (turnaround - frame_tail).div_ceil(clocks_per_bit);
+                warn!(
+                    "Receiver takes {} clocks besides the start, data and parity bits to get ready for the next start bit, which is more than {} clocks of the stop bit and idle time; Back-to-back frames need --idle-bits {} on the sender",
+                    turnaround, frame_tail, suggested_idle_bits
+                );
+                rx_turnaround = Some(RxTurnaround { clocks: turnaround, frame_tail_clocks: frame_tail, suggested_idle_bits });
+            }
+        }
+
+        // Receiver samples the middle of the stop bit, by then the accumulated timing error should
+        // stay within half of a bit
+        let sampled_bits = frame_bits as f64 + 0.5;
+        let sampling_offset_clocks = match (rx, timer) {
+            (None, _) => 0.0,
+            // Start bit is found up to a tick late
+            (Some(_), Some(setup)) => {
+                let jitter = match (tx, tx_buffer_size) {
+                    (None, _) => 0,
+                    (Some(_), None) => TIMER_RX_SAMPLE_JITTER_CLOCKS,
+                    (Some(_), Some(_)) => TIMER_RX_SAMPLE_JITTER_CLOCKS + TIMER_TX_BUFFER_JITTER_CLOCKS,
+                };
+                setup.period_clocks as f64 / 2.0 + jitter as f64
+            }
+            // Timeout variant polls less often than `uart{N}_rx_wait_ready`
+            (Some(_), None) => {
+                let detection = if rx_timeout { RX_TIMEOUT_POLL_CLOCKS } else { RX_START_DETECTION_CLOCKS };
+                detection as f64 + (clocks_per_half_bit as f64 - expected_clocks_per_bit / 2.0).abs()
+            }
+        };
+        let rounding_percent = clock_derivation * 100f64;
+        let sampling_percent = sampling_offset_clocks / expected_clocks_per_bit * 100f64
+            / sampled_bits;
+        let clock_tolerance_percent = config.clock_tolerance
+            .unwrap_or(DEFAULT_CLOCK_TOLERANCE_PERCENT);
+        let error_budget = ErrorBudget {
+            rounding_percent,
+            sampling_percent,
+            clock_tolerance_percent,
+            total_percent: rounding_percent + sampling_percent + clock_tolerance_percent,
+            allowed_percent: 50f64 / sampled_bits,
+        };
+
+        info!(
+            "Timing error budget: {:.2}% of {:.2}% tolerated by the receiver",
+            error_budget.total_percent,
+            error_budget.allowed_percent
+        );
+
+        if error_budget.total_percent > error_budget.allowed_percent {
+            return Err(Error::ErrorBudgetExceeded(error_budget));
+        }
+
+        let generator = UartGenerator {
             frequency,
             baud,
             clocks_per_bit,
             clocks_per_stop_bit,
             clocks_per_half_bit,
-            tx_port,
-            tx_pin,
+            fractional_timing,
+            clock_deviation: clock_derivation,
+            error_budget,
+            idle_clocks,
+            data_bits,
+            parity,
+            tx,
             uart_num,
             invert_tx,
-            rx_port,
-            rx_pin,
+            rx,
             invert_rx,
-        })
+            half_duplex: config.half_duplex,
+            echo_check: config.echo_check,
+            rx_interrupt: config.rx_interrupt,
+            rx_buffer_size,
+            timer,
+            ticks_per_bit,
+            tx_buffer_size,
+            rx_timeout: if rx_timeout { Some(RxTimeout::new(clocks_per_bit)) } else { None },
+            rx_turnaround,
+            autobaud: config.autobaud,
+            baud_table,
+            template,
+        };
+
+        let mut pins = PinAllocator::new(config.device);
+        for claim in generator.pin_claims() {
+            pins.claim(claim)?;
+        }
+
+        if generator.timer.is_some() {
+            let tx_frame_bits = generator.timer_tx_frame_bits();
+            if tx.is_some() && tx_frame_bits > TIMER_MAX_TX_FRAME_BITS {
+                return Err(Error::TimerUnsupported("frames longer than 255 bits"));
+            }
+        }
+
+        // Generated code should fit into the device RAM, the timer ISR into its period, and the
+        // baud rates into the autobaud range and the runtime wait range
+        if generator.timer.is_some() || generator.runtime_waits() || config.device.is_some() {
+            let (_, report) = generator.generate_with_report(false)?;
+            if let Some(autobaud) = report.autobaud {
+                if !(autobaud.min_clocks_per_bit..=autobaud.max_clocks_per_bit).contains(&clocks_per_bit) {
+                    return Err(Error::AutobaudBaudOutOfRange(baud, autobaud.min_baud, autobaud.max_baud));
+                }
+            }
+            if let Some(waits) = report.runtime_waits {
+                let range = waits.min_clocks_per_bit..=waits.max_clocks_per_bit;
+                if let Some(entry) = generator.baud_table.iter().find(|entry| !range.contains(&entry.clocks_per_bit)) {
+                    return Err(Error::BaudTableOutOfRange(entry.baud, waits.min_baud, waits.max_baud));
+                }
+            }
+            if let (Some(device), Some(ram_bytes)) = (config.device, report.ram_bytes) {
+                device.check_ram(ram_bytes)?;
+            }
+            if let Some(timer) = report.timer {
+                if timer.isr_clocks > timer.setup.period_clocks {
+                    return Err(Error::TimerIsrTooLong(timer.isr_clocks, timer.setup.period_clocks));
+                }
+            }
+        }
+
+        Ok(generator)
     }
 }
 
+/// Values available to the templates
+///
+/// Custom templates rely on these fields, so they are a stable contract: Any incompatible change
+/// (removed or renamed field, changed meaning) requires bumping `TEMPLATE_VERSION`. Names passed as
+/// `*_name` are C symbols; In the inline assembly they are prefixed with `_`.
 #[derive(Serialize)]
 struct TemplateContext {
     app_name: &'static str,
     app_version: &'static str,
 
+    /// MCU frequency, Hz
     frequency: u32,
     baud: u32,
 
     tx_function_name: String,
+    tx_enabled: bool,
     tx_port: char,
     tx_pin: u8,
     tx_inverted: bool,
-    tx_start_bit_wait_cycles: u32,
-    tx_start_bit_tail_wait_instructions: Vec<&'static str>,
-    tx_bit_wait_cycles: u32,
-    tx_bit_tail_wait_instructions: Vec<&'static str>,
-    tx_stop_bit_wait_cycles: u32,
-    tx_stop_bit_tail_wait_instructions: Vec<&'static str>,
+    /// `1 << tx_pin`, for the instructions that can't take an expression
+    tx_pin_mask: u8,
+    /// Instructions driving the TX line to the mark (idle) and space levels
+    tx_mark_instruction: String,
+    tx_space_instruction: String,
+    tx_echo_check: bool,
+    /// Variable set to non-zero when the line doesn't match the sent bit
+    tx_collision_name: String,
+    tx_nine_bits: bool,
+    tx_data_bits: u8,
+    tx_parity: bool,
+    /// Parity is counted from the data bits, otherwise it is constant (mark or space)
+    tx_parity_counted: bool,
+    tx_parity_initial_value: u8,
+    tx_parity_carry_instructions: Vec<String>,
+    /// Delay instructions padding each section to its exact clocks count
+    tx_start_bit_wait_instructions: Vec<String>,
+    tx_bit_wait_instructions: Vec<String>,
+    tx_stop_bit_lag_instructions: Vec<String>,
+    tx_stop_bit_wait_instructions: Vec<String>,
 
     rx_function_name: String,
+    /// Variable receiving the data byte
     rx_byte_name: String,
+    rx_enabled: bool,
     rx_port: char,
     rx_pin: u8,
     rx_inverted: bool,
-    rx_start_bit_wait_cycles: u32,
-    rx_start_bit_tail_wait_instructions: Vec<&'static str>,
-    rx_bit_wait_cycles: u32,
-    rx_bit_tail_wait_instructions: Vec<&'static str>,
+    rx_nine_bits: bool,
+    /// Variable receiving the 9th data bit
+    rx_bit8_name: String,
+    /// Data bits shifted into `rx_byte_name`
+    rx_loop_bits: u8,
+    rx_parity: bool,
+    rx_parity_counted: bool,
+    rx_parity_expected_odd: bool,
+    rx_parity_wait_instructions: Vec<String>,
+    rx_start_bit_wait_instructions: Vec<String>,
+    rx_bit_wait_instructions: Vec<String>,
+    rx_align_instructions: Vec<String>,
+
+    /// Start bit edge interrupt receives into the ring buffer
+    rx_interrupt: bool,
+    /// Called by the application interrupt handler
+    rx_isr_function_name: String,
+    /// Enters `rx_function_name` code after the early check and the ISR latency padding
+    rx_isr_entry_name: String,
+    /// Bit of the RX pin in `INTEN` and `INTRQ`
+    rx_interrupt_bit: u8,
+    /// Padding of the polling path, which the ISR skips
+    rx_isr_lead_instructions: Vec<String>,
+    rx_available_function_name: String,
+    rx_read_function_name: String,
+    /// Ring buffer array; Its head and tail indices are suffixed `_head` and `_tail`
+    rx_buffer_name: String,
+    /// Power of two, the buffer holds one word less
+    rx_buffer_size: u8,
+    rx_buffer_mask: u8,
+    /// Variables set to non-zero when a word is dropped or a frame is invalid
+    rx_overflow_name: String,
+    rx_error_name: String,
+
+    /// Start bit wait of `rx_timeout_function_name` gives up after the given number of ticks
+    rx_timeout: bool,
+    rx_timeout_function_name: String,
+    /// Prefix of the macros converting bits and microseconds to ticks, e.g. `UART0_RX_TIMEOUT`
+    rx_timeout_macro_prefix: String,
+    /// Poll counter value; One more poll is taken by the tick counter update
+    rx_timeout_poll_count: u32,
+    rx_timeout_ticks_per_bit: u32,
+    rx_timeout_tick_clocks: u32,
+    /// Tick length, rounded to nanoseconds
+    rx_timeout_tick_ns: u32,
+
+    /// Timer interrupt clocks the bits, see `UART_TIMER_IMPLEMENTATION_TEMPLATE`
+    timer_enabled: bool,
+    /// Register and interrupt name prefix, e.g. `TM2`
+    timer_name: &'static str,
+    timer_t16: bool,
+    /// Called by the application interrupt handler
+    timer_isr_function_name: String,
+    timer_period_clocks: u32,
+    timer_ticks_per_bit: u32,
+    timer_prescaler: u32,
+    timer_scaler: u32,
+    /// Register values: T16M, TM2S/TM3S and TM2B/TM3B
+    timer_mode_value: u8,
+    timer_scaler_value: u8,
+    timer_bound_value: u8,
+    /// T16 counter value stored by the ISR
+    timer_reload_value: u16,
+    /// Bits sent by the ISR per frame, including stop and idle bits
+    tx_frame_bits: u32,
+    /// Frames are queued into the ring buffer by `tx_write_function_name` and taken by the ISR
+    tx_buffered: bool,
+    tx_write_function_name: String,
+    tx_flush_function_name: String,
+    /// Ring buffer array; Its head and tail indices are suffixed `_head` and `_tail`
+    tx_buffer_name: String,
+    tx_buffer_size: u8,
+    tx_buffer_mask: u8,
+    /// Set when the buffer is empty and the last stop bit is sent
+    tx_complete_name: String,
+    /// Function framing the word and its parameter, and the variable receiving the frame
+    tx_frame_function_name: String,
+    tx_frame_name: String,
+    /// Word bits above the data and parity bits, set to send the stop bits
+    tx_stop_mask_low: u8,
+    tx_stop_mask_high: u8,
+    /// Sets the parity bit of the frame word
+    tx_frame_parity_instructions: Vec<String>,
+    /// Samples of the frame: start, data, parity and stop bits
+    rx_samples: u32,
+    /// Shift the frame samples to get the start bit in carry and data aligned to LSB
+    rx_frame_align_instructions: Vec<String>,
+    /// Counts ones of the data and parity bits of the aligned frame
+    rx_frame_parity_instructions: Vec<String>,
+    rx_decode_function_name: String,
+    /// Low byte mask of the data bits, applied when there are less than 8
+    data_mask: u8,
+    data_masked: bool,
+    /// Received words are read with `rx_available_function_name`/`rx_read_function_name`
+    rx_buffered: bool,
+
+    /// Bit waits call the runtime wait routines with the period set in RAM, either measured by
+    /// `autobaud_function_name` or selected by `set_baud_function_name`
+    runtime_waits: bool,
+    autobaud: bool,
+    autobaud_function_name: String,
+    /// Sync character, e.g. `0x55`, and its falling edges after the start bit one
+    autobaud_sync: String,
+    autobaud_falling_edges: u8,
+    runtime_wait_bit_function_name: String,
+    runtime_wait_half_bit_function_name: String,
+    /// Bit and half bit waits: 8T loops and the rest of clocks
+    runtime_bit_wait_name: String,
+    runtime_half_bit_wait_name: String,
+    /// Loop and rest counters of the wait routines
+    runtime_wait_counter_name: String,
+    /// Subtracted from the measured period to get the bit wait
+    runtime_wait_base: u32,
+    /// Smallest half bit period, which takes a single wait loop
+    autobaud_min_half_bit: u32,
+    runtime_initial_bit_loops: u8,
+    runtime_initial_bit_rest: u8,
+    runtime_initial_half_bit_loops: u8,
+    runtime_initial_half_bit_rest: u8,
+    /// Runtime waits of each baud rate, in ROM
+    baud_table_enabled: bool,
+    baud_table_name: String,
+    baud_table_size: usize,
+    baud_table: Vec<BaudTableContext>,
+    /// Prefix of the index defines, e.g. `UART0_BAUD` for `UART0_BAUD_9600`
+    baud_macro_prefix: String,
+    set_baud_function_name: String,
 
     init_function_name: String,
     rx_wait_ready_function_name: String,
+    half_duplex: bool,
+
+    /// Counter bytes shared by the delay loops
+    delay_counter_name: String,
+    delay_counter_required: bool,
+    delay_counter_size: u8,
+
+    /// Bit waits step the phase accumulator, which should be declared
+    fractional_timing: bool,
+    phase_name: String,
+
+    /// Functions are local to the single-file output, split output exports them via header
+    function_storage: &'static str,
+    header_name: String,
+    include_guard: String,
+}
+
+#[derive(Serialize)]
+struct BaudTableContext {
+    index: usize,
+    baud: u32,
+    /// Index define, e.g. `UART0_BAUD_9600`
+    macro_name: String,
+    bit_loops: u8,
+    bit_rest: u8,
+    half_bit_loops: u8,
+    half_bit_rest: u8,
 }
 
-const UART_TEMPLATE: &str = r##"// THIS FILE WAS GENERATED BY {app_name} v{app_version}
+/// Root of the file templates, which are composed of the parts rendered with the `uart` context
+#[derive(Serialize)]
+struct FileTemplateContext<'a> {
+    uart: &'a TemplateContext,
+}
+
+const UART_BANNER_TEMPLATE: &str = r##"// THIS FILE WAS GENERATED BY {app_name} v{app_version}
 // Target F_CPU: {frequency};  Target baud: {baud}
-// TX pin: P{tx_port}{tx_pin}; TX Inverted: {tx_inverted}
-#include <stdint.h>
-#include <pdk/device.h>
+{{if half_duplex}}// Half-duplex line: P{tx_port}{tx_pin} (open-drain, requires pull-up)
+{{else}}{{if tx_enabled}}// TX pin: P{tx_port}{tx_pin}; TX Inverted: {tx_inverted}
+{{endif}}{{if rx_enabled}}// RX pin: P{rx_port}{rx_pin}; RX Inverted: {rx_inverted}
+{{endif}}{{endif}}"##;
 
+const UART_F_CPU_CHECK_TEMPLATE: &str = r##"
 #ifndef F_CPU
     #error "Generated uart required F_CPU to be set"
 #endif
@@ -246,176 +987,960 @@ const UART_TEMPLATE: &str = r##"// THIS FILE WAS GENERATED BY {app_name} v{app_v
 #if F_CPU != {frequency}
     #error "Defined F_CPU does not match generated uart's frequency ({frequency})"
 #endif
+"##;
 
+// Result codes are shared by all generated UARTs
+const UART_DEFINITIONS_TEMPLATE: &str = r##"{{if rx_enabled}}
+#ifndef UART_RESULT_RX_IDLE
 #define UART_RESULT_RX_IDLE 0
 #define UART_RESULT_RX_RECEIVED 1
 #define UART_RESULT_RX_ERROR 2
+#define UART_RESULT_RX_PARITY_ERROR 3
 
 typedef uint8_t UartResult;
+#endif
+{{endif}}{{if rx_timeout}}
+// Ticks of `{rx_timeout_function_name}` for the timeout in bits or microseconds, up to 65535
+#define {rx_timeout_macro_prefix}_BITS(bits) ((uint16_t)((bits) * {rx_timeout_ticks_per_bit}))
+#define {rx_timeout_macro_prefix}_US(us) ((uint16_t)(((uint32_t)(us) * 1000 + {rx_timeout_tick_ns} - 1) / {rx_timeout_tick_ns}))
+{{endif}}{{if baud_table_enabled}}
+// Indexes of `{set_baud_function_name}`
+{{for entry in baud_table}}#define {entry.macro_name} {entry.index}
+{{endfor}}{{endif}}{{if tx_buffered}}
+#ifndef UART_TX_OK
+#define UART_TX_OK 0
+#define UART_TX_FULL 1
+#endif
+{{endif}}"##;
+
+const UART_INCLUDES_TEMPLATE: &str = r##"#include <stdint.h>
+#include <pdk/device.h>
+"##;
+
+const UART_HEADER_TEMPLATE: &str = r##"{{call banner with uart}}#ifndef {uart.include_guard}
+#define {uart.include_guard}
+
+#include <stdint.h>
+{{call definitions with uart}}
+void {uart.init_function_name}(void);
+{{if uart.baud_table_enabled}}void {uart.set_baud_function_name}(uint8_t index);
+{{endif}}{{if uart.tx_enabled}}void {uart.tx_function_name}({{if uart.tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte);
+{{if uart.tx_echo_check}}extern uint8_t {uart.tx_collision_name};
+{{endif}}{{if uart.tx_buffered}}uint8_t {uart.tx_write_function_name}({{if uart.tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte);
+void {uart.tx_flush_function_name}(void);
+extern volatile uint8_t {uart.tx_complete_name};
+{{endif}}{{endif}}{{if uart.timer_enabled}}void {uart.timer_isr_function_name}(void);
+{{endif}}{{if uart.rx_enabled}}{{if uart.timer_enabled}}{{else}}uint8_t {uart.rx_wait_ready_function_name}(void);
+UartResult {uart.rx_function_name}(void);
+{{if uart.rx_timeout}}UartResult {uart.rx_timeout_function_name}(uint16_t ticks);
+{{endif}}{{if uart.autobaud}}UartResult {uart.autobaud_function_name}(void);
+{{endif}}extern uint8_t {uart.rx_byte_name};
+{{if uart.rx_nine_bits}}extern uint8_t {uart.rx_bit8_name};
+{{endif}}{{endif}}{{if uart.rx_interrupt}}void {uart.rx_isr_function_name}(void);
+{{endif}}{{if uart.rx_buffered}}uint8_t {uart.rx_available_function_name}(void);
+{{if uart.rx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} {uart.rx_read_function_name}(void);
+extern volatile uint8_t {uart.rx_overflow_name};
+extern volatile uint8_t {uart.rx_error_name};
+{{endif}}{{endif}}
+#endif // {uart.include_guard}
+"##;
 
-static void {init_function_name}(void) \{
-    {{if tx_inverted}}// Set tx pin to low (inverted mode)
+const UART_SOURCE_TEMPLATE: &str = r##"{{call banner with uart}}{{call includes with uart}}#include "{uart.header_name}"
+{{call f_cpu_check with uart}}{{call implementation with uart}}"##;
+
+const UART_IMPLEMENTATION_TEMPLATE: &str = r##"{{if delay_counter_required}}
+// Counter for the long delay loops
+static uint8_t {delay_counter_name}[{delay_counter_size}];
+{{endif}}{{if fractional_timing}}
+// Accumulated fraction of the bit period, 1/256 of a clock
+static uint8_t {phase_name};
+{{endif}}{{if runtime_waits}}
+// Runtime waits of the bit and half bit period: 8T loops and the rest of clocks
+static uint8_t {runtime_bit_wait_name}[2];
+static uint8_t {runtime_half_bit_wait_name}[2];
+static uint8_t {runtime_wait_counter_name}[2];
+{{endif}}
+{function_storage}void {init_function_name}(void) \{
+{{if half_duplex}}    // Output latch is always low; line is pulled low by switching pin to output
+    P{tx_port} &= ~(1 << {tx_pin});
+    // Release the line (input mode) and enable pull-up
+    P{tx_port}C &= ~(1 << {tx_pin});
+    P{tx_port}PH |= (1 << {tx_pin});
+    P{tx_port}DIER |= (1 << {tx_pin});
+{{else}}{{if tx_enabled}}    {{if tx_inverted}}// Set tx pin to low (inverted mode)
     P{tx_port} &= ~(1 << {tx_pin});
     {{else}}// Set tx pin to high
     P{tx_port} |= (1 << {tx_pin});
     {{endif}}// Set tx as output pin
     P{tx_port}C |= (1 << {tx_pin});
-
-    // Set port as input pin
+{{endif}}{{if rx_enabled}}{{if tx_enabled}}
+{{endif}}    // Set port as input pin
     P{rx_port}C &= ~(1 << {rx_pin});
     P{rx_port}DIER |= (1 << {rx_pin});
-}
+{{if rx_interrupt}}    // Interrupt on the start bit edge; INTEGS is write-only, it sets edges of all interrupt pins
+    INTEGS = INTEGS_P{rx_port}{rx_pin}_{{if rx_inverted}}RISING{{else}}FALLING{{endif}};
+    INTRQ &= ~INTRQ_P{rx_port}{rx_pin};
+    INTEN |= INTEN_P{rx_port}{rx_pin};
+{{endif}}{{endif}}{{endif}}{{if runtime_waits}}
+    // Waits of the initial baud rate, {baud} baud
+    {runtime_bit_wait_name}[0] = {runtime_initial_bit_loops};
+    {runtime_bit_wait_name}[1] = {runtime_initial_bit_rest};
+    {runtime_half_bit_wait_name}[0] = {runtime_initial_half_bit_loops};
+    {runtime_half_bit_wait_name}[1] = {runtime_initial_half_bit_rest};
+{{endif}}}
+{{if baud_table_enabled}}
+// Runtime waits of each baud rate: bit loops and rest, half bit loops and rest
+static const uint8_t {baud_table_name}[{baud_table_size}][4] = \{{{for entry in baud_table}}
+    \{ {entry.bit_loops}, {entry.bit_rest}, {entry.half_bit_loops}, {entry.half_bit_rest} }, // {entry.baud}{{endfor}}
+};
 
-uint8_t {rx_wait_ready_function_name}(void) __naked \{
+// Selects the baud rate by its {baud_macro_prefix}_<rate> index; Call it between frames
+{function_storage}void {set_baud_function_name}(uint8_t index) \{
+    {runtime_bit_wait_name}[0] = {baud_table_name}[index][0];
+    {runtime_bit_wait_name}[1] = {baud_table_name}[index][1];
+    {runtime_half_bit_wait_name}[0] = {baud_table_name}[index][2];
+    {runtime_half_bit_wait_name}[1] = {baud_table_name}[index][3];
+}
+{{endif}}{{if runtime_waits}}
+// Both take {runtime_wait_base}T less than the period, including the call
+static void {runtime_wait_half_bit_function_name}(void) __naked \{
     __asm
-    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
-    goto .-1
-    ret #0
+    mov a, _{runtime_half_bit_wait_name} ; 1T
+    mov _{runtime_wait_counter_name}, a ; 1T
+    mov a, _{runtime_half_bit_wait_name}+1 ; 1T
+    mov _{runtime_wait_counter_name}+1, a ; 1T
+    goto _gen_label_{runtime_wait_bit_function_name}_loop ; 2T
     __endasm;
 }
 
-static uint8_t _gen_{tx_function_name}_bits_left;
-
-static void {tx_function_name}(uint8_t byte) \{
+static void {runtime_wait_bit_function_name}(void) __naked \{
     __asm
-    ; start bit
-    {{if tx_inverted}}set1{{else}}set0{{endif}} P{tx_port}_ADDR, #{tx_pin} ; 1T
-    mov a, #{tx_start_bit_wait_cycles} ; 1T
-    0001$: ; wait loop takes ({tx_start_bit_wait_cycles} * 4 - 1)T
+    mov a, _{runtime_bit_wait_name} ; 1T
+    mov _{runtime_wait_counter_name}, a ; 1T
+    mov a, _{runtime_bit_wait_name}+1 ; 1T
+    mov _{runtime_wait_counter_name}+1, a ; 1T
+    goto .+1 ; 2T
+
+    ; 8T per loop, 7T the last one
+    _gen_label_{runtime_wait_bit_function_name}_loop:
+    goto .+1 ; 2T
+    goto .+1 ; 2T
     nop ; 1T
-    dzsn a ; Normally 1T, 2T in last cycle
-    goto 0001$ ; 2T
-    mov a, #8 ; 1T
-    mov __gen_{tx_function_name}_bits_left, a ; 1T
-    {{for instruction in tx_start_bit_tail_wait_instructions}}{instruction}
-    {{endfor}}
+    dzsn _{runtime_wait_counter_name} ; 1T/2T on skip
+    goto _gen_label_{runtime_wait_bit_function_name}_loop ; 2T
 
-    ; send 1 bit; compare (0002$ -- 0004$) will take 8T
+    ; Rest of 0..7 clocks, by its bits; Takes 10T more
+    t0sn _{runtime_wait_counter_name}+1, #0 ; 2T on skip
+    goto .+1 ; 2T
+    t0sn _{runtime_wait_counter_name}+1, #1 ; 2T on skip
+    goto 0001$ ; 2T
+    goto 0002$ ; 2T
+    0001$:
+    goto .+1 ; 2T
+    nop ; 1T
     0002$:
-    sr _{tx_function_name}_PARM_1 ; 1T, carry flag will contain LSB
+    t0sn _{runtime_wait_counter_name}+1, #2 ; 2T on skip
+    goto 0003$ ; 2T
+    goto 0004$ ; 2T
+    0003$:
+    goto .+1 ; 2T
+    goto .+1 ; 2T
+    nop ; 1T
+    0004$:
+    ret ; 2T
+    __endasm;
+}
+{{endif}}{{if tx_enabled}}
+static uint8_t _gen_{tx_function_name}_bits_left;
+{{if tx_echo_check}}static uint8_t _gen_{tx_function_name}_echo;
+uint8_t {tx_collision_name};
+{{endif}}{{if tx_parity_counted}}static uint8_t _gen_{tx_function_name}_parity;
+{{endif}}
+{function_storage}void {tx_function_name}({{if tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte) \{
+    __asm
+    ; start bit
+    {tx_space_instruction} ; 1T{{for instruction in tx_start_bit_wait_instructions}}
+    {instruction}{{endfor}}
+    mov a, #{tx_data_bits} ; 1T
+    mov __gen_{tx_function_name}_bits_left, a ; 1T{{if tx_echo_check}}
+    mov a, #255 ; 1T
+    mov __gen_{tx_function_name}_echo, a ; 1T, line bit will be cleared on collision{{endif}}{{if tx_parity_counted}}
+    mov a, #{tx_parity_initial_value} ; 1T
+    mov __gen_{tx_function_name}_parity, a ; 1T{{endif}}
+
+    ; send 1 bit; compare will take {{if tx_nine_bits}}8T{{else}}7T{{endif}}
+    0002$:{{if tx_nine_bits}}
+    sr _{tx_function_name}_PARM_1+1 ; 1T, carry flag will contain 9th bit
+    src _{tx_function_name}_PARM_1 ; 1T, 9th bit goes to MSB, carry flag will contain LSB{{else}}
+    sr _{tx_function_name}_PARM_1 ; 1T, carry flag will contain LSB{{endif}}
     t1sn f, c ; 1T when bit is 0, in other case - 2T
     goto .+4 ; 2T
-    nop ; 1T
-    {{if tx_inverted}}set0{{else}}set1{{endif}} P{tx_port}_ADDR, #{tx_pin} ; 1T
+    {{if tx_parity_counted}}inc __gen_{tx_function_name}_parity ; 1T, count ones for parity{{else}}nop ; 1T{{endif}}
+    {tx_mark_instruction} ; 1T
     goto .+3 ; 2T
-    {{if tx_inverted}}set1{{else}}set0{{endif}} P{tx_port}_ADDR, #{tx_pin} ; 1T
-    goto .+1 ; 2T goto isntead of nop to equalify branches
-    mov a, #{tx_bit_wait_cycles} ; 1T
-    0004$: ; wait loop takes ({tx_bit_wait_cycles} * 4 - 1)T
-    nop ; 1T
-    dzsn a ; 1T normally, 2T on skip
-    goto 0004$ ; 2T
-    {{for instruction in tx_bit_tail_wait_instructions}}{instruction}
-    {{endfor}}
+    {tx_space_instruction} ; 1T
+    goto .+1 ; 2T goto instead of nop to equalize branches{{for instruction in tx_bit_wait_instructions}}
+    {instruction}{{endfor}}
 
     ; check for more bits; following chunk will take 3T in any case
     dzsn __gen_{tx_function_name}_bits_left ; 1T normally, 2T on skip
     goto 0002$ ; 2T
-    nop ; 1T
+    nop ; 1T{{if tx_parity}}
 
-    ; wait + 5T to adjust lag from the code above
-    goto .+1 ; 2T
-    goto .+1 ; 2T
+    ; send parity bit; takes the same time as data bit, except the bit counter check{{for instruction in tx_parity_carry_instructions}}
+    {instruction}{{endfor}}
+    t1sn f, c ; 1T when bit is 0, in other case - 2T
+    goto .+4 ; 2T
     nop ; 1T
+    {tx_mark_instruction} ; 1T
+    goto .+3 ; 2T
+    {tx_space_instruction} ; 1T
+    goto .+1 ; 2T{{for instruction in tx_bit_wait_instructions}}
+    {instruction}{{endfor}}{{endif}}
+
+    ; adjust lag from the code above{{for instruction in tx_stop_bit_lag_instructions}}
+    {instruction}{{endfor}}
 
     ; send stop bit
-    {{if tx_inverted}}set0{{else}}set1{{endif}} P{tx_port}_ADDR, #{tx_pin} ; 1T
-    MOV a, #15 ; 1T
-    0005$: ; wait loop takes ({tx_stop_bit_wait_cycles} * 4 - 1)
-    nop ; 1T
-    dzsn a ; 1T normally, 2T on skip
-    goto 0005$ ; 2T
-    {{for instruction in tx_stop_bit_tail_wait_instructions}}{instruction}
-    {{endfor}}
+    {tx_mark_instruction} ; 1T{{for instruction in tx_stop_bit_wait_instructions}}
+    {instruction}{{endfor}}{{if tx_echo_check}}
+
+    ; report collision
+    mov a, __gen_{tx_function_name}_echo ; 1T
+    not a ; 1T
+    and a, #{tx_pin_mask} ; 1T
+    mov _{tx_collision_name}, a ; 1T{{endif}}
+    __endasm;
+}
+{{endif}}{{if rx_enabled}}
+uint8_t {rx_wait_ready_function_name}(void) __naked \{
+    __asm
+    ; wait for start bit
+    {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
+    goto .-1
+    ret #0
     __endasm;
 }
 
 uint8_t {rx_byte_name};
-uint8_t _gen_{rx_function_name}_bit;
-
-static UartResult {rx_function_name}(void) __naked \{
+{{if rx_nine_bits}}uint8_t {rx_bit8_name};
+{{endif}}uint8_t _gen_{rx_function_name}_bit;
+{{if rx_parity}}uint8_t _gen_{rx_function_name}_parity;
+{{endif}}
+{function_storage}UartResult {rx_function_name}(void) __naked \{
     __asm
     ; Early check (A&F are not affected)
     {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T on skip/start bit
-    ret #UART_RESULT_RX_IDLE
+    ret #UART_RESULT_RX_IDLE{{if rx_interrupt}}
+
+    ; Time the interrupt takes to reach the ISR entry (A&F are not affected){{for instruction in rx_isr_lead_instructions}}
+    {instruction}{{endfor}}
+    _gen_label_{rx_function_name}_isr_entry:{{endif}}
 
     ; Function prelude
-    pushaf ; 1T
+    pushaf ; 1T{{if rx_parity}}
+    clear __gen_{rx_function_name}_parity ; 1T{{endif}}
 
-    ; Wait to middle of the bit
-    mov a, #{rx_start_bit_wait_cycles} ; 1T
-    nop ; 1T
-    dzsn a ; 1T normally, 2T on skip
-    goto .-2 ; 2T
-    {{for instruction in rx_start_bit_tail_wait_instructions}}{instruction}
-    {{endfor}}
+    ; Wait to middle of the bit{{for instruction in rx_start_bit_wait_instructions}}
+    {instruction}{{endfor}}
 
     ; Validate start bit mid-value
     {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T on skip/start bit
     goto _gen_label_{rx_function_name}_error ; 2T
 
     ; Set bit counter to initial value
-    mov a, #8 ; 1T, loop will end on 9th bit (after dec 0)
+    mov a, #{rx_loop_bits} ; 1T, loop will end on the bit after the last counted one (after dec 0)
     mov __gen_{rx_function_name}_bit, a ; 1T
+    {{if rx_parity_counted}}set0 f, c ; 1T, nothing to count on the first iteration; {{else}}nop ; 1T, {{endif}}first bit sample is 1T closer than samples in the loop
 
     ; Bit loop
     _gen_label_{rx_function_name}_bit_loop:
-    src _{rx_byte_name} ; 1T; insert bit from carry (from the previous iteration)
-    ; Wait loop
-    mov a, #{rx_bit_wait_cycles} ; 1T
-    nop ; 1T
-    dzsn a; 1T normall, 2T on skip
-    goto .-2 ; 2T
-    {{for instruction in rx_bit_tail_wait_instructions}}{instruction}
-    {{endfor}}
+    src _{rx_byte_name} ; 1T; insert bit from carry (from the previous iteration){{if rx_parity_counted}}
+    t0sn _{rx_byte_name}, #7 ; 1T/2T on skip
+    inc __gen_{rx_function_name}_parity ; 1T, count ones for parity (inc affects carry){{endif}}
+    ; Wait loop{{for instruction in rx_bit_wait_instructions}}
+    {instruction}{{endfor}}
 
-    ; check rx bit value; code beforea actual check introduces 4T lag
+    ; check rx bit value, carry will contain the logical bit value; code beforea actual check introduces 4T lag
     dec __gen_{rx_function_name}_bit ; 1T; decrease count of remainig bits
-    {{if rx_inverted}}set0{{else}}set1{{endif}} f, c ; 1T
+    set1 f, c ; 1T
     {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T, read rx bit
-    {{if rx_inverted}}set1{{else}}set0{{endif}} f, c ; 1T
+    set0 f, c ; 1T
 
-    ; check bit counter; 0xFF value (7th bit is set) represents 9th iteration
+    ; check bit counter; 0xFF value (7th bit is set) represents the last iteration
     t1sn __gen_{rx_function_name}_bit, #7 ; 1T normally, 2T loop exit
     goto _gen_label_{rx_function_name}_bit_loop ; 2T
-    nop ; 1T
+    nop ; 1T{{if rx_nine_bits}}
+
+    ; 9th bit is in carry; store it in place of the shift and the bit counter decrement (2T)
+    clear _{rx_bit8_name} ; 1T
+    slc _{rx_bit8_name} ; 1T{{if rx_parity_counted}}
+    t0sn _{rx_bit8_name}, #0 ; 1T/2T on skip
+    inc __gen_{rx_function_name}_parity ; 1T, count ones for parity{{endif}}
+    ; Wait loop{{for instruction in rx_bit_wait_instructions}}
+    {instruction}{{endfor}}
+
+    ; check {{if rx_parity}}parity{{else}}stop{{endif}} bit value
+    set1 f, c ; 1T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T, read rx bit
+    set0 f, c ; 1T{{endif}}{{if rx_parity}}
+
+    ; parity bit is in carry
+    t0sn f, c ; 1T/2T on skip
+    inc __gen_{rx_function_name}_parity ; 1T, count parity bit{{for instruction in rx_parity_wait_instructions}}
+    {instruction}{{endfor}}
+
+    ; check stop bit value
+    set1 f, c ; 1T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T, read rx bit
+    set0 f, c ; 1T{{endif}}
 
     ; Validate stop bit value
-    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} f, c ; 1T/2T
-    goto _gen_label_{rx_function_name}_error ; 2T
+    t1sn f, c ; 1T/2T
+    goto _gen_label_{rx_function_name}_error ; 2T{{if rx_parity}}
+
+    ; Validate parity; LSB of the counter is expected to be {{if rx_parity_expected_odd}}1{{else}}0{{endif}}
+    {{if rx_parity_expected_odd}}t1sn{{else}}t0sn{{endif}} __gen_{rx_function_name}_parity, #0 ; 1T/2T
+    goto _gen_label_{rx_function_name}_parity_error ; 2T{{endif}}{{for instruction in rx_align_instructions}}
+    {instruction}{{endfor}}
     popaf ; 1T
     ret #UART_RESULT_RX_RECEIVED ; 2T
     _gen_label_{rx_function_name}_error:
     popaf
-    ret #UART_RESULT_RX_ERROR ; 2T; start/stop bits were invalid
+    ret #UART_RESULT_RX_ERROR ; 2T; start/stop bits were invalid{{if rx_parity}}
+    _gen_label_{rx_function_name}_parity_error:
+    popaf
+    ret #UART_RESULT_RX_PARITY_ERROR ; 2T{{endif}}
+    __endasm;
+}
+{{if rx_timeout}}
+static uint8_t _gen_{rx_timeout_function_name}_polls;
+
+// Waits for the start bit up to `ticks` of {rx_timeout_tick_clocks} clocks and receives the frame
+// like {rx_function_name}; Returns UART_RESULT_RX_IDLE when the time is out
+{function_storage}UartResult {rx_timeout_function_name}(uint16_t ticks) __naked \{
+    __asm
+    ; The line is polled each 5T or sooner, also between the counter updates
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 2T on idle line
+    goto _{rx_function_name} ; 2T, receive function returns to the caller
+
+    ; Ticks are counted up from the complement, the counter overflows after the last one
+    not _{rx_timeout_function_name}_PARM_1 ; 1T
+    not _{rx_timeout_function_name}_PARM_1+1 ; 1T
+    mov a, #{rx_timeout_poll_count} ; 1T, A keeps the poll counter reload value
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
+    goto _{rx_function_name}
+    goto 0002$ ; 2T
+
+    0001$:
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 2T on idle line
+    goto _{rx_function_name}
+    dzsn __gen_{rx_timeout_function_name}_polls ; 1T/2T on skip
+    goto 0001$ ; 2T
+
+    ; Next tick, takes one poll
+    0002$:
+    mov __gen_{rx_timeout_function_name}_polls, a ; 1T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 2T on idle line
+    goto _{rx_function_name}
+    izsn _{rx_timeout_function_name}_PARM_1 ; 1T/2T on overflow
+    goto 0001$ ; 2T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
+    goto _{rx_function_name}
+    izsn _{rx_timeout_function_name}_PARM_1+1
+    goto 0001$
+    ret #UART_RESULT_RX_IDLE
     __endasm;
 }
+{{endif}}{{if autobaud}}
+static uint8_t _gen_{autobaud_function_name}_count[2];
+static uint8_t _gen_{autobaud_function_name}_edges;
+
+// Waits for the {autobaud_sync} sync character and sets the bit waits to its period; Returns
+// UART_RESULT_RX_ERROR and keeps the previous waits when the period is out of range
+{function_storage}UartResult {autobaud_function_name}(void) __naked \{
+    __asm
+    ; wait for start bit
+    {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
+    goto .-1
+    clear __gen_{autobaud_function_name}_count ; 1T
+    clear __gen_{autobaud_function_name}_count+1 ; 1T
+    mov a, #{autobaud_falling_edges} ; 1T
+    mov __gen_{autobaud_function_name}_edges, a ; 1T
 
+    ; Count 8T loops until the last falling edge, 8 bits after the start one
+    0001$:
+    goto .+1 ; 2T
+    0002$:
+    nop ; 1T
+    inc __gen_{autobaud_function_name}_count ; 1T
+    addc __gen_{autobaud_function_name}_count+1 ; 1T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 2T on mark
+    goto 0001$ ; 2T
+    nop ; 1T
+    0003$:
+    goto .+1 ; 2T
+    nop ; 1T
+    inc __gen_{autobaud_function_name}_count ; 1T
+    addc __gen_{autobaud_function_name}_count+1 ; 1T
+    {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 2T on space
+    goto 0003$ ; 2T
+    dzsn __gen_{autobaud_function_name}_edges ; 1T/2T on the last edge
+    goto 0002$ ; 2T, 16T with the next loop
+
+    ; Count is the bit period in clocks; Wait for the stop bit
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin}
+    goto .-1
+
+    ; Bit wait: period - {runtime_wait_base}, up to 2047 clocks
+    mov a, __gen_{autobaud_function_name}_count
+    mov _{runtime_wait_counter_name}, a
+    mov a, __gen_{autobaud_function_name}_count+1
+    mov _{runtime_wait_counter_name}+1, a
+    mov a, #{runtime_wait_base}
+    sub _{runtime_wait_counter_name}, a
+    subc _{runtime_wait_counter_name}+1
+    t0sn f, c
+    goto 0004$
+    mov a, _{runtime_wait_counter_name}+1
+    and a, #0xF8
+    t1sn f, z
+    goto 0004$
+
+    ; Half bit wait: period / 2 - {runtime_wait_base}, at least one loop
+    sr __gen_{autobaud_function_name}_count+1
+    src __gen_{autobaud_function_name}_count
+    mov a, #{autobaud_min_half_bit}
+    sub __gen_{autobaud_function_name}_count, a
+    subc __gen_{autobaud_function_name}_count+1
+    t0sn f, c
+    goto 0004$
+    mov a, #8
+    add __gen_{autobaud_function_name}_count, a
+    addc __gen_{autobaud_function_name}_count+1
+
+    ; Split both into the rest of clocks and 8T loops
+    mov a, _{runtime_wait_counter_name}
+    and a, #7
+    mov _{runtime_bit_wait_name}+1, a
+    mov a, __gen_{autobaud_function_name}_count
+    and a, #7
+    mov _{runtime_half_bit_wait_name}+1, a
+    mov a, #3
+    mov __gen_{autobaud_function_name}_edges, a
+    0005$:
+    sr _{runtime_wait_counter_name}+1
+    src _{runtime_wait_counter_name}
+    sr __gen_{autobaud_function_name}_count+1
+    src __gen_{autobaud_function_name}_count
+    dzsn __gen_{autobaud_function_name}_edges
+    goto 0005$
+    mov a, _{runtime_wait_counter_name}
+    mov _{runtime_bit_wait_name}, a
+    mov a, __gen_{autobaud_function_name}_count
+    mov _{runtime_half_bit_wait_name}, a
+    ret #UART_RESULT_RX_RECEIVED
+
+    0004$:
+    ret #UART_RESULT_RX_ERROR
+    __endasm;
+}
+{{endif}}{{if rx_interrupt}}
+static UartResult {rx_isr_entry_name}(void) __naked \{
+    __asm
+    goto _gen_label_{rx_function_name}_isr_entry ; 2T
+    __endasm;
+}
+
+static {{if rx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} {rx_buffer_name}[{rx_buffer_size}];
+static volatile uint8_t {rx_buffer_name}_head;
+static volatile uint8_t {rx_buffer_name}_tail;
+volatile uint8_t {rx_overflow_name};
+volatile uint8_t {rx_error_name};
+
+// Call from the interrupt handler on the start bit edge:
+// if (INTRQ & INTRQ_P{rx_port}{rx_pin}) {rx_isr_function_name}();
+{function_storage}void {rx_isr_function_name}(void) __naked \{
+    __asm
+    call _{rx_isr_entry_name}
+    ; edges of the received frame have requested the interrupt again
+    set0 INTRQ_ADDR, #{rx_interrupt_bit} ; INTRQ_P{rx_port}{rx_pin}
+    ceqsn a, #UART_RESULT_RX_RECEIVED
+    goto 0001$
+    mov a, _{rx_buffer_name}_head
+    add a, #1
+    and a, #{rx_buffer_mask}
+    cneqsn a, _{rx_buffer_name}_tail
+    goto 0002$
+
+    ; append to the buffer, the head is moved after the word is stored
+    mov a, _{rx_buffer_name}_head{{if rx_nine_bits}}
+    sl a ; words{{endif}}
+    add a, #(_{rx_buffer_name})
+    mov p, a
+    clear p+1
+    mov a, _{rx_byte_name}
+    idxm p, a{{if rx_nine_bits}}
+    inc p
+    mov a, _{rx_bit8_name}
+    idxm p, a{{endif}}
+    mov a, _{rx_buffer_name}_head
+    add a, #1
+    and a, #{rx_buffer_mask}
+    mov _{rx_buffer_name}_head, a
+    ret
+
+    0001$:
+    set1 _{rx_error_name}, #0
+    ret
+    0002$:
+    set1 _{rx_overflow_name}, #0
+    ret
+    __endasm;
+}
+
+{function_storage}uint8_t {rx_available_function_name}(void) __naked \{
+    __asm
+    mov a, _{rx_buffer_name}_head
+    sub a, _{rx_buffer_name}_tail
+    and a, #{rx_buffer_mask}
+    ret
+    __endasm;
+}
+
+// Takes the oldest received word; Buffer should not be empty
+{function_storage}{{if rx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} {rx_read_function_name}(void) __naked \{
+    __asm
+    mov a, _{rx_buffer_name}_tail{{if rx_nine_bits}}
+    sl a ; words{{endif}}
+    add a, #(_{rx_buffer_name})
+    mov p, a
+    clear p+1
+    idxm a, p
+    pushaf{{if rx_nine_bits}}
+    inc p
+    idxm a, p
+    mov p, a ; high byte of the result{{endif}}
+
+    ; the slot is released after the word is taken
+    mov a, _{rx_buffer_name}_tail
+    add a, #1
+    and a, #{rx_buffer_mask}
+    mov _{rx_buffer_name}_tail, a
+    popaf
+    ret
+    __endasm;
+}
+{{endif}}{{endif}}
 "##;
 
+// Timer-driven backend: the ISR clocks a bit (or a third of a bit when RX is generated) per timer
+// interrupt, so interrupts stay enabled and the code runs in the background
+const UART_TIMER_IMPLEMENTATION_TEMPLATE: &str = r##"{{if tx_enabled}}
+static uint8_t _gen_{tx_function_name}_shift[2];
+static uint8_t _gen_{tx_function_name}_bits_left;
+static uint8_t _gen_{tx_function_name}_divider;
+{{if tx_parity_counted}}static uint8_t _gen_{tx_function_name}_parity;
+{{endif}}{{if tx_buffered}}// Framed words waiting for the ISR, the buffer holds one word less than its size
+static uint16_t {tx_buffer_name}[{tx_buffer_size}];
+static uint8_t {tx_buffer_name}_head;
+static uint8_t {tx_buffer_name}_tail;
+static uint8_t {tx_frame_name}[2];
+volatile uint8_t {tx_complete_name};
+{{endif}}{{endif}}{{if rx_enabled}}
+static uint8_t _gen_{rx_function_name}_shift[2];
+static uint8_t _gen_{rx_function_name}_bits_left;
+static uint8_t _gen_{rx_function_name}_divider;
+// Samples of the last received frame, taken over by `{rx_decode_function_name}`
+static uint8_t _gen_{rx_function_name}_frame[2];
+static uint8_t _gen_{rx_function_name}_ready;
+static uint8_t _gen_{rx_function_name}_word[2];
+{{if rx_parity}}static uint8_t _gen_{rx_function_name}_parity;
+{{endif}}static uint8_t {rx_byte_name};
+{{if rx_nine_bits}}static uint8_t {rx_bit8_name};
+{{endif}}volatile uint8_t {rx_overflow_name};
+volatile uint8_t {rx_error_name};
+{{endif}}{{if timer_t16}}static uint16_t _gen_{timer_isr_function_name}_reload;
+{{endif}}
+{function_storage}void {init_function_name}(void) \{
+{{if tx_enabled}}    {{if tx_inverted}}// Set tx pin to low (inverted mode)
+    P{tx_port} &= ~(1 << {tx_pin});
+    {{else}}// Set tx pin to high
+    P{tx_port} |= (1 << {tx_pin});
+    {{endif}}// Set tx as output pin
+    P{tx_port}C |= (1 << {tx_pin});
+    _gen_{tx_function_name}_bits_left = 0;
+    _gen_{tx_function_name}_divider = 1;
+{{if tx_buffered}}    {tx_buffer_name}_head = 0;
+    {tx_buffer_name}_tail = 0;
+    {tx_complete_name} = 1;
+{{endif}}{{endif}}{{if rx_enabled}}{{if tx_enabled}}
+{{endif}}    // Set port as input pin
+    P{rx_port}C &= ~(1 << {rx_pin});
+    P{rx_port}DIER |= (1 << {rx_pin});
+    _gen_{rx_function_name}_bits_left = 0;
+    _gen_{rx_function_name}_ready = 0;
+{{endif}}
+    // Interrupt every {timer_period_clocks} clocks, {timer_ticks_per_bit} per bit; Global interrupts are enabled by the application
+{{if timer_t16}}    _gen_{timer_isr_function_name}_reload = {timer_reload_value};
+    __asm__("stt16 __gen_{timer_isr_function_name}_reload");
+    T16M = {timer_mode_value}; // SYSCLK/{timer_prescaler}, interrupt on bit 15 rising
+{{else}}    {timer_name}CT = 0;
+    {timer_name}B = {timer_bound_value};
+    {timer_name}S = {timer_scaler_value}; // prescaler /{timer_prescaler}, scaler /{timer_scaler}
+    {timer_name}C = 0x10; // SYSCLK, period mode, no output
+{{endif}}    INTRQ &= ~INTRQ_{timer_name};
+    INTEN |= INTEN_{timer_name};
+}
+
+// Call from the interrupt handler on the timer interrupt:
+// if (INTRQ & INTRQ_{timer_name}) \{ INTRQ &= ~INTRQ_{timer_name}; {timer_isr_function_name}(); }
+{function_storage}void {timer_isr_function_name}(void) \{
+    __asm{{if timer_t16}}
+    ; move the counter back by the period
+    stt16 __gen_{timer_isr_function_name}_reload ; 1T{{endif}}{{if tx_enabled}}
+
+    ; TX goes first, so the line changes at the same offset from the interrupt
+    dzsn __gen_{tx_function_name}_divider ; 1T/2T on bit boundary
+    goto 0010$ ; 2T
+    mov a, #{timer_ticks_per_bit} ; 1T
+    mov __gen_{tx_function_name}_divider, a ; 1T
+    mov a, __gen_{tx_function_name}_bits_left ; 1T
+    cneqsn a, #0 ; 1T/2T when sending
+    goto {{if tx_buffered}}0011${{else}}0010${{endif}} ; 2T
+    dec __gen_{tx_function_name}_bits_left ; 1T
+    set1 f, c ; 1T, mark is shifted in after the frame
+    src __gen_{tx_function_name}_shift+1 ; 1T
+    src __gen_{tx_function_name}_shift ; 1T, carry flag will contain the bit
+    t1sn f, c ; 1T when bit is 0, in other case - 2T
+    goto .+4 ; 2T
+    nop ; 1T
+    {tx_mark_instruction} ; 1T
+    goto .+3 ; 2T
+    {tx_space_instruction} ; 1T
+    goto .+1 ; 2T goto instead of nop to equalize branches{{if tx_buffered}}
+    goto 0010$ ; 2T
+    0011$:
+    set1 _{tx_complete_name}, #0 ; 1T, line is idle, so the last frame is complete{{endif}}
+    0010$:{{endif}}{{if rx_enabled}}
+
+    ; RX
+    mov a, __gen_{rx_function_name}_bits_left ; 1T
+    cneqsn a, #0 ; 1T/2T when receiving
+    goto 0020$ ; 2T
+    dzsn __gen_{rx_function_name}_divider ; 1T/2T on sample
+    goto 0030$ ; 2T
+    mov a, #{timer_ticks_per_bit} ; 1T
+    mov __gen_{rx_function_name}_divider, a ; 1T
+    set1 f, c ; 1T
+    {{if rx_inverted}}t0sn{{else}}t1sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T, read rx bit
+    set0 f, c ; 1T
+    src __gen_{rx_function_name}_shift+1 ; 1T, samples are shifted in from MSB
+    src __gen_{rx_function_name}_shift ; 1T
+    dzsn __gen_{rx_function_name}_bits_left ; 1T/2T on the last sample
+    goto 0030$ ; 2T
+
+    ; pass the frame to {rx_decode_function_name}
+    t0sn __gen_{rx_function_name}_ready, #0 ; 1T/2T
+    set1 _{rx_overflow_name}, #0 ; 1T, previous frame was not taken
+    mov a, __gen_{rx_function_name}_shift ; 1T
+    mov __gen_{rx_function_name}_frame, a ; 1T
+    mov a, __gen_{rx_function_name}_shift+1 ; 1T
+    mov __gen_{rx_function_name}_frame+1, a ; 1T
+    set1 __gen_{rx_function_name}_ready, #0 ; 1T
+    goto 0030$ ; 2T
+
+    ; wait for start bit; its first sample is taken on the next tick, close to the middle
+    0020$:
+    {{if rx_inverted}}t1sn{{else}}t0sn{{endif}} P{rx_port}_ADDR, #{rx_pin} ; 1T/2T on start bit
+    goto 0030$ ; 2T
+    mov a, #{rx_samples} ; 1T
+    mov __gen_{rx_function_name}_bits_left, a ; 1T
+    mov a, #1 ; 1T
+    mov __gen_{rx_function_name}_divider, a ; 1T
+    0030$:{{endif}}{{if tx_buffered}}
+
+    ; take the next frame once the last bit of the previous one is on the line, it is sent from the
+    ; next bit boundary
+    mov a, __gen_{tx_function_name}_bits_left ; 1T
+    ceqsn a, #0 ; 1T/2T when idle
+    goto 0040$ ; 2T
+    mov a, _{tx_buffer_name}_tail ; 1T
+    cneqsn a, _{tx_buffer_name}_head ; 1T/2T when not empty
+    goto 0040$ ; 2T
+    sl a ; 1T, words
+    add a, #(_{tx_buffer_name}) ; 1T
+    mov p, a ; 1T
+    clear p+1 ; 1T
+    idxm a, p ; 2T
+    mov __gen_{tx_function_name}_shift, a ; 1T
+    inc p ; 1T
+    idxm a, p ; 2T
+    mov __gen_{tx_function_name}_shift+1, a ; 1T
+    mov a, _{tx_buffer_name}_tail ; 1T
+    add a, #1 ; 1T
+    and a, #{tx_buffer_mask} ; 1T
+    mov _{tx_buffer_name}_tail, a ; 1T
+    mov a, #{tx_frame_bits} ; 1T
+    mov __gen_{tx_function_name}_bits_left, a ; 1T
+    clear _{tx_complete_name} ; 1T
+    0040$:{{endif}}
+    __endasm;
+}
+{{if tx_enabled}}{{if tx_buffered}}
+// Queues the word for sending; Returns `UART_TX_FULL` when there is no room in the buffer
+{function_storage}uint8_t {tx_write_function_name}({{if tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte) __naked \{
+    __asm
+    mov a, _{tx_buffer_name}_head
+    add a, #1
+    and a, #{tx_buffer_mask}
+    ceqsn a, _{tx_buffer_name}_tail
+    goto 0001$
+    ret #UART_TX_FULL
+    0001$:
+{{else}}
+{function_storage}void {tx_function_name}({{if tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte) \{
+    __asm
+    ; wait for the ISR to send the previous frame
+    0001$:
+    mov a, __gen_{tx_function_name}_bits_left
+    ceqsn a, #0
+    goto 0001$
+{{endif}}
+    ; data and parity bits, the rest of the register is mark
+    mov a, _{tx_frame_function_name}_PARM_1{{if data_masked}}
+    and a, #{data_mask}{{endif}}
+    mov _{tx_frame_name}, a{{if tx_nine_bits}}
+    mov a, _{tx_frame_function_name}_PARM_1+1
+    and a, #1
+    mov _{tx_frame_name}+1, a{{else}}
+    clear _{tx_frame_name}+1{{endif}}{{for instruction in tx_frame_parity_instructions}}
+    {instruction}{{endfor}}
+    mov a, #{tx_stop_mask_low}
+    or _{tx_frame_name}, a
+    mov a, #{tx_stop_mask_high}
+    or _{tx_frame_name}+1, a
+
+    ; start bit
+    sl _{tx_frame_name}
+    slc _{tx_frame_name}+1
+{{if tx_buffered}}
+    ; append to the buffer, the ISR takes it when the line is free
+    mov a, _{tx_buffer_name}_head
+    sl a
+    add a, #(_{tx_buffer_name})
+    mov p, a
+    clear p+1
+    mov a, _{tx_frame_name}
+    idxm p, a
+    inc p
+    mov a, _{tx_frame_name}+1
+    idxm p, a
+    mov a, _{tx_buffer_name}_head
+    add a, #1
+    and a, #{tx_buffer_mask}
+    mov _{tx_buffer_name}_head, a
+    clear _{tx_complete_name}
+    ret #UART_TX_OK
+    __endasm;
+}
+
+{function_storage}void {tx_function_name}({{if tx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} byte) \{
+    while ({tx_write_function_name}(byte) != UART_TX_OK);
+}
+
+// Waits until the buffer is empty and the stop bit of the last frame is sent
+{function_storage}void {tx_flush_function_name}(void) \{
+    while (!{tx_complete_name});
+}
+{{else}}
+    ; ISR starts sending on the next bit boundary
+    mov a, #{tx_frame_bits}
+    mov __gen_{tx_function_name}_bits_left, a
+    __endasm;
+}
+{{endif}}{{endif}}{{if rx_enabled}}
+static UartResult {rx_decode_function_name}(void) __naked \{
+    __asm
+    t1sn __gen_{rx_function_name}_ready, #0
+    ret #UART_RESULT_RX_IDLE
+
+    ; take the frame over with interrupts untouched; The ISR could replace it during the copy,
+    ; then the copy is repeated and the replaced frame is reported as overflow
+    0001$:
+    clear __gen_{rx_function_name}_ready
+    mov a, __gen_{rx_function_name}_frame
+    mov __gen_{rx_function_name}_word, a
+    mov a, __gen_{rx_function_name}_frame+1
+    mov __gen_{rx_function_name}_word+1, a
+    t1sn __gen_{rx_function_name}_ready, #0
+    goto 0002$
+    set1 _{rx_overflow_name}, #0
+    goto 0001$
+    0002$:
+
+    ; stop bit is the last sample
+    t1sn __gen_{rx_function_name}_word+1, #7
+    ret #UART_RESULT_RX_ERROR
+
+    ; shift the start bit out to carry, data bits are aligned to LSB{{for instruction in rx_frame_align_instructions}}
+    {instruction}{{endfor}}
+    t0sn f, c
+    ret #UART_RESULT_RX_ERROR{{if rx_parity}}
+
+    ; validate parity; LSB of the counter is expected to be {{if rx_parity_expected_odd}}1{{else}}0{{endif}}{{for instruction in rx_frame_parity_instructions}}
+    {instruction}{{endfor}}
+    {{if rx_parity_expected_odd}}t1sn{{else}}t0sn{{endif}} __gen_{rx_function_name}_parity, #0
+    ret #UART_RESULT_RX_PARITY_ERROR{{endif}}
+
+    mov a, __gen_{rx_function_name}_word{{if data_masked}}
+    and a, #{data_mask}{{endif}}
+    mov _{rx_byte_name}, a{{if rx_nine_bits}}
+    mov a, __gen_{rx_function_name}_word+1
+    and a, #1
+    mov _{rx_bit8_name}, a{{endif}}
+    ret #UART_RESULT_RX_RECEIVED
+    __endasm;
+}
+
+{function_storage}uint8_t {rx_available_function_name}(void) \{
+    return _gen_{rx_function_name}_ready;
+}
+
+// Takes the received word, invalid frames set `{rx_error_name}`; Check `{rx_available_function_name}()` first
+{function_storage}{{if rx_nine_bits}}uint16_t{{else}}uint8_t{{endif}} {rx_read_function_name}(void) \{
+    if ({rx_decode_function_name}() != UART_RESULT_RX_RECEIVED) \{
+        {rx_error_name} = 1;
+    }
+    return {{if rx_nine_bits}}((uint16_t){rx_bit8_name} << 8) | {{endif}}{rx_byte_name};
+}
+{{endif}}"##;
+
+/// Single-file template, the parts are concatenated so it could be edited as a whole
+fn single_file_template(implementation: &str) -> String {
+    [
+        UART_BANNER_TEMPLATE,
+        UART_INCLUDES_TEMPLATE,
+        UART_F_CPU_CHECK_TEMPLATE,
+        UART_DEFINITIONS_TEMPLATE,
+        implementation,
+    ].concat()
+}
+
+/// Built-in single-file template with the version header, a starting point for custom templates
+pub fn builtin_template() -> String {
+    format!(
+        "{}{}\n{}",
+        TEMPLATE_HEADER,
+        TEMPLATE_VERSION,
+        single_file_template(UART_IMPLEMENTATION_TEMPLATE)
+    )
+}
+
+/// Checks the version header of the custom template and strips it
+fn parse_template(template: &str) -> Result<String, Error> {
+    let (header, body) = template.split_once('\n').unwrap_or((template, ""));
+    let version = header.trim_end()
+        .strip_prefix(TEMPLATE_HEADER)
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or(Error::TemplateHeaderMissing)?;
+    if version != TEMPLATE_VERSION {
+        return Err(Error::TemplateVersionMismatch(version));
+    }
+    Ok(body.to_string())
+}
+
+/// Bit durations which track the exact (fractional) bit period
+///
+/// Bits take `base` or `base + 1` clocks; The generated code adds `step / 256` of a clock to the
+/// phase accumulator on each bit and takes the extra clock when it overflows.
+#[derive(Clone, Copy, Debug)]
+struct FractionalTiming {
+    base: u32,
+    step: u32,
+}
+
+impl FractionalTiming {
+    fn new(expected_clocks_per_bit: f64) -> Self {
+        let base = expected_clocks_per_bit.floor() as u32;
+        let step = ((expected_clocks_per_bit - base as f64) * 256.0).round() as u32;
+        if step == 256 {
+            Self { base: base + 1, step: 0 }
+        } else {
+            Self { base, step }
+        }
+    }
+
+    /// Clocks from the start of the first bit to the end of the `bits`-th one; Accumulator starts
+    /// from the half, so the edges are rounded to the nearest clock
+    fn offset(&self, bits: u32) -> u32 {
+        bits * self.base + (128 + bits * self.step) / 256
+    }
+}
+
+/// Time the polled receiver takes from the start bit detection to the next start bit polling,
+/// when it is longer than the stop bit and idle time of the frame
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct RxTurnaround {
+    pub clocks: u32,
+    pub frame_tail_clocks: u32,
+    /// Idle time the sender should add, so back-to-back frames are not lost
+    pub suggested_idle_bits: u32,
+}
+
+/// Timeout of `uart{N}_receive_timeout` is counted in ticks of a few polls of the start bit; A bit
+/// takes a whole number of ticks, so the timeout could be given in bits
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct RxTimeout {
+    pub ticks_per_bit: u32,
+    pub polls_per_tick: u32,
+    pub tick_clocks: u32,
+}
+
+impl RxTimeout {
+    fn new(clocks_per_bit: u32) -> Self {
+        let ticks_per_bit = clocks_per_bit.div_ceil(RX_TIMEOUT_MAX_POLLS * RX_TIMEOUT_POLL_CLOCKS);
+        let polls_per_tick = (clocks_per_bit as f64 / (ticks_per_bit * RX_TIMEOUT_POLL_CLOCKS) as f64)
+            .round()
+            .clamp(2.0, RX_TIMEOUT_MAX_POLLS as f64) as u32;
+        Self {
+            ticks_per_bit,
+            polls_per_tick,
+            tick_clocks: polls_per_tick * RX_TIMEOUT_POLL_CLOCKS,
+        }
+    }
+}
+
 pub struct UartGenerator {
     frequency: Frequency,
     baud: u32,
     clocks_per_bit: u32,
     clocks_per_half_bit: u32,
     clocks_per_stop_bit: u32,
-    tx_port: Port,
-    tx_pin: Pin,
+    fractional_timing: Option<FractionalTiming>,
+    clock_deviation: f64,
+    error_budget: ErrorBudget,
+    idle_clocks: u32,
+    data_bits: DataBits,
+    parity: Parity,
+    tx: Option<(Port, Pin)>,
     uart_num: u8,
     invert_tx: bool,
-    rx_port: Port,
-    rx_pin: Pin,
+    rx: Option<(Port, Pin)>,
     invert_rx: bool,
-}
-
-fn generate_space_optimal_nop_chain(count: u32) -> Vec<&'static str> {
-    match count {
-        0 => vec![],
-        1 => vec!["nop ; 1T"],
-        2 => vec!["goto .+1 ; 2T"],
-        3 => vec!["goto .+1 ; 2T", "nop ; 1T"],
-        _ => panic!("Function designed to work only with 4T wait loops"),
-    }
+    half_duplex: bool,
+    echo_check: bool,
+    rx_interrupt: bool,
+    rx_buffer_size: u8,
+    timer: Option<TimerSetup>,
+    /// Timer interrupts per bit
+    ticks_per_bit: u32,
+    tx_buffer_size: Option<u8>,
+    rx_timeout: Option<RxTimeout>,
+    rx_turnaround: Option<RxTurnaround>,
+    autobaud: Option<SyncChar>,
+    baud_table: Vec<BaudTableEntry>,
+    template: Option<String>,
 }
 
 impl UartGenerator {
@@ -423,96 +1948,547 @@ impl UartGenerator {
         UartGeneratorBuilder::default()
     }
 
-    pub fn generate(&self) -> Result<String, Error> {
-        const WAIT_LOOP_MISSING_LOCKS: u32 = 1;
-        const TX_SET_WAIT_LOOP_COUNTER_CLOCKS: u32 = 1;
-        const TX_SET_PIN_CLOCKS: u32 = 1;
+    pub fn frequency(&self) -> Frequency {
+        self.frequency
+    }
 
-        const TX_BIT_SET_LOOP_LAG_CLOCKS: u32 = 5;
-        const TX_RESET_BIT_COUNTER_CLOCKS: u32 = 2;
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
 
-        let tx_start_bit_wait_clocks = self.clocks_per_bit
-            - TX_BIT_SET_LOOP_LAG_CLOCKS
-            - TX_SET_WAIT_LOOP_COUNTER_CLOCKS
-            - TX_SET_PIN_CLOCKS
-            - TX_RESET_BIT_COUNTER_CLOCKS
-            + WAIT_LOOP_MISSING_LOCKS;
+    pub fn clocks_per_bit(&self) -> u32 {
+        self.clocks_per_bit
+    }
 
-        let tx_start_bit_wait_cycles = tx_start_bit_wait_clocks / 4;
-        let tx_start_bit_tail_wait_cycles = tx_start_bit_wait_clocks % 4;
-        let tx_start_bit_tail_wait_instructions =
-            generate_space_optimal_nop_chain(tx_start_bit_tail_wait_cycles);
+    pub fn clocks_per_stop_bit(&self) -> u32 {
+        self.clocks_per_stop_bit
+    }
 
-        const TX_BIT_COMPARE_AND_SET_PIN_CLOCKS: u32 = 8;
-        const TX_COMPARE_BIT_COUNT_CLOCKS: u32 = 3;
+    /// Clocks from the start bit edge to the end of the `bits`-th bit of the frame
+    pub fn bit_offset(&self, bits: u32) -> u32 {
+        match self.fractional_timing {
+            Some(timing) => timing.offset(bits),
+            None => bits * self.clocks_per_bit,
+        }
+    }
 
-        let tx_bit_wait_clocks = self.clocks_per_bit
-            - TX_BIT_COMPARE_AND_SET_PIN_CLOCKS
-            - TX_SET_WAIT_LOOP_COUNTER_CLOCKS
-            - TX_COMPARE_BIT_COUNT_CLOCKS
-            + WAIT_LOOP_MISSING_LOCKS;
+    /// Relative baud rate error due to the rounding of clocks per bit
+    pub fn clock_deviation(&self) -> f64 {
+        self.clock_deviation
+    }
+
+    pub fn error_budget(&self) -> ErrorBudget {
+        self.error_budget
+    }
+
+    pub fn data_bits(&self) -> DataBits {
+        self.data_bits
+    }
+
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
 
-        let tx_bit_wait_cycles = tx_bit_wait_clocks / 4;
-        let tx_bit_tail_wait_cycles = tx_bit_wait_clocks % 4;
-        let tx_bit_tail_wait_instructions =
-            generate_space_optimal_nop_chain(tx_bit_tail_wait_cycles);
+    /// Extra idle line time after the stop bit
+    pub fn idle_clocks(&self) -> u32 {
+        self.idle_clocks
+    }
 
-        let tx_stop_bit_wait_clocks = self.clocks_per_stop_bit
-            - TX_BIT_SET_LOOP_LAG_CLOCKS
+    pub fn tx(&self) -> Option<(Port, Pin)> {
+        self.tx
+    }
+
+    pub fn rx(&self) -> Option<(Port, Pin)> {
+        self.rx
+    }
+
+    pub fn tx_inverted(&self) -> bool {
+        self.invert_tx
+    }
+
+    pub fn rx_inverted(&self) -> bool {
+        self.invert_rx
+    }
+
+    pub fn half_duplex(&self) -> bool {
+        self.half_duplex
+    }
+
+    pub fn echo_check(&self) -> bool {
+        self.echo_check
+    }
+
+    /// RX is done by `uart{N}_rx_isr` into the ring buffer
+    pub fn rx_interrupt(&self) -> bool {
+        self.rx_interrupt
+    }
+
+    /// Timer which clocks the bits from `uart{N}_timer_isr`
+    pub fn timer(&self) -> Option<TimerSetup> {
+        self.timer
+    }
+
+    /// Timer interrupts per bit
+    pub fn ticks_per_bit(&self) -> u32 {
+        self.ticks_per_bit
+    }
+
+    /// TX ring buffer size, words are queued by `uart{N}_write`
+    pub fn tx_buffer_size(&self) -> Option<u8> {
+        self.tx_buffer_size
+    }
+
+    /// Tick length of `uart{N}_receive_timeout`
+    pub fn rx_timeout(&self) -> Option<RxTimeout> {
+        self.rx_timeout
+    }
+
+    /// Set when the receiver can't return in time for back-to-back frames
+    pub fn rx_turnaround(&self) -> Option<RxTurnaround> {
+        self.rx_turnaround
+    }
+
+    /// Sync character measured by `uart{N}_autobaud`
+    pub fn autobaud(&self) -> Option<SyncChar> {
+        self.autobaud
+    }
+
+    /// Baud rates selected by `uart{N}_set_baud`, empty when there is no table
+    pub fn baud_table(&self) -> &[BaudTableEntry] {
+        &self.baud_table
+    }
+
+    /// Bit waits take their clocks from RAM, set by `uart{N}_autobaud` or `uart{N}_set_baud`
+    fn runtime_waits(&self) -> bool {
+        self.autobaud.is_some() || !self.baud_table.is_empty()
+    }
+
+    /// Start, data and parity bits; Stop bit begins right after them
+    fn frame_bits(&self) -> u32 {
+        1 + self.data_bits.count() as u32 + (self.parity != Parity::None) as u32
+    }
+
+    /// Bits sent by the timer ISR per frame, including stop and idle bits
+    fn timer_tx_frame_bits(&self) -> u32 {
+        self.frame_bits() + (self.clocks_per_stop_bit + self.idle_clocks) / self.clocks_per_bit
+    }
+
+    /// Pins used by the generated code
+    pub fn pin_claims(&self) -> Vec<PinClaim> {
+        let owner = self.file_stem();
+        let claim = |function, (port, pin), usage| PinClaim {
+            owner: owner.clone(),
+            function,
+            port,
+            pin,
+            usage,
+        };
+        match (self.tx, self.rx) {
+            (Some(line), _) if self.half_duplex => vec![claim("line", line, PinUsage::OpenDrain)],
+            (tx, rx) => tx.map(|tx| claim("TX", tx, PinUsage::Output))
+                .into_iter()
+                .chain(rx.map(|rx| claim("RX", rx, PinUsage::Input)))
+                .collect(),
+        }
+    }
+
+    /// Name of the generated files without extension, e.g. `uart0`
+    pub fn file_stem(&self) -> String {
+        format!("uart{}", self.uart_num)
+    }
+
+    /// Name of the generated symbol, e.g. `uart0_send` for `send`
+    pub fn symbol_name(&self, name: &str) -> String {
+        format!("uart{0}_{1}", self.uart_num, name)
+    }
+
+    /// Generates single self-contained C file
+    pub fn generate(&self) -> Result<String, Error> {
+        self.generate_with_report(false).map(|(files, _)| files.source)
+    }
+
+    /// Generates the code along with the summary of its timings and resource usage; With `split`
+    /// public declarations are placed into a separate header
+    pub fn generate_with_report(&self, split: bool) -> Result<(GeneratedFiles, UartReport), Error> {
+        let delay_counter_name = format!("_gen_{}", self.symbol_name("delay"));
+        let delay_counter_asm_name = format!("_{}", delay_counter_name);
+        let delay = |clocks: u32| Delay::new(clocks, &delay_counter_asm_name);
+
+        let data_bits = self.data_bits.count();
+        let nine_bits = data_bits == 9;
+
+        let (tx_port, tx_pin) = self.tx
+            .map(|(port, pin)| (port.char(), pin.num()))
+            .unwrap_or_default();
+
+        // Line sampling for echo check; line bit of `P | PC` is low only when the line is released
+        // but somebody else pulls it low
+        const TX_ECHO_SAMPLE_CLOCKS: u32 = 3;
+        let echo_sample = [
+            format!("mov a, P{}C_ADDR ; 1T", tx_port),
+            format!("or a, P{}_ADDR ; 1T, sample line", tx_port),
+            format!("and __gen_{}_echo, a ; 1T", self.symbol_name("send")),
+        ];
+        // `sample_offset` is the desired count of clocks before the sample instruction
+        let wait_with_echo_sample = |clocks: u32, sample_offset: u32| -> Delay {
+            if !self.echo_check || clocks < TX_ECHO_SAMPLE_CLOCKS {
+                return delay(clocks);
+            }
+            let before = sample_offset.min(clocks - TX_ECHO_SAMPLE_CLOCKS);
+            let mut wait = delay(before);
+            wait.push_code(echo_sample.iter().cloned());
+            wait.append(delay(clocks - TX_ECHO_SAMPLE_CLOCKS - before));
+            wait
+        };
+
+        // With fractional timing each bit wait starts with the phase accumulator step, which takes
+        // an extra clock on overflow
+        const PHASE_STEP_CLOCKS: u32 = 4;
+        const PHASE_INIT_CLOCKS: u32 = 2;
+        let phase_name = format!("_gen_{}", self.symbol_name("phase"));
+        let (bit_clocks, phase_step_clocks, phase_init_clocks) = match self.fractional_timing {
+            Some(timing) => (timing.base, PHASE_STEP_CLOCKS, PHASE_INIT_CLOCKS),
+            None => (self.clocks_per_bit, 0, 0),
+        };
+        let with_code = |code: Vec<String>, wait: Delay| -> Delay {
+            let mut padded = delay(0);
+            padded.push_code(code);
+            padded.append(wait);
+            padded
+        };
+        let phase_step = |wait: Delay| match self.fractional_timing {
+            Some(timing) => with_code(vec![
+                format!("mov a, #{} ; 1T", timing.step),
+                format!("add _{}, a ; 1T, carry is set when the fraction adds up to a clock", phase_name),
+                "t0sn f, c ; 1T/2T on skip".to_string(),
+                "goto .+1 ; 2T, extra clock".to_string(),
+            ], wait),
+            None => wait,
+        };
+        let phase_init = |phase: u32, wait: Delay| match self.fractional_timing {
+            Some(_) => with_code(vec![
+                format!("mov a, #{} ; 1T", phase),
+                format!("mov _{}, a ; 1T, initial phase", phase_name),
+            ], wait),
+            None => wait,
+        };
+
+        let (tx_mark_instruction, tx_space_instruction) = if self.half_duplex {
+            // Open-drain: line is released for mark and driven low for space
+            (format!("set0 P{}C_ADDR, #{}", tx_port, tx_pin), format!("set1 P{}C_ADDR, #{}", tx_port, tx_pin))
+        } else if self.invert_tx {
+            (format!("set0 P{}_ADDR, #{}", tx_port, tx_pin), format!("set1 P{}_ADDR, #{}", tx_port, tx_pin))
+        } else {
+            (format!("set1 P{}_ADDR, #{}", tx_port, tx_pin), format!("set0 P{}_ADDR, #{}", tx_port, tx_pin))
+        };
+        let parity = self.parity != Parity::None;
+        // Even/odd parity requires counting of the data bits
+        let parity_counted = matches!(self.parity, Parity::Even | Parity::Odd);
+
+        const TX_SET_PIN_CLOCKS: u32 = 1;
+
+        // 9-bit value is shifted as 16-bit, which takes additional 1T
+        let tx_shift_clocks = if nine_bits { 2 } else { 1 };
+        let tx_bit_set_loop_lag_clocks = 3 + tx_shift_clocks;
+        const TX_RESET_BIT_COUNTER_CLOCKS: u32 = 2;
+        let tx_reset_parity_counter_clocks = if parity_counted { 2 } else { 0 };
+        let tx_reset_echo_clocks = if self.echo_check { 2 } else { 0 };
+
+        // Start bit is the first step of the phase accumulator, it is done here
+        let tx_initial_phase = self.fractional_timing
+            .map(|timing| (128 + timing.step) % 256)
+            .unwrap_or_default();
+        let tx_start_bit_wait = phase_init(tx_initial_phase, delay(self.bit_offset(1)
+            - tx_bit_set_loop_lag_clocks
             - TX_SET_PIN_CLOCKS
-            - TX_SET_WAIT_LOOP_COUNTER_CLOCKS
-            + WAIT_LOOP_MISSING_LOCKS;
+            - TX_RESET_BIT_COUNTER_CLOCKS
+            - tx_reset_parity_counter_clocks
+            - tx_reset_echo_clocks
+            - phase_init_clocks));
 
-        let tx_stop_bit_wait_cycles = tx_stop_bit_wait_clocks / 4;
-        let tx_stop_bit_tail_wait_cycles = tx_stop_bit_wait_clocks % 4;
-        let tx_stop_bit_tail_wait_instructions =
-            generate_space_optimal_nop_chain(tx_stop_bit_tail_wait_cycles);
+        let tx_bit_compare_and_set_pin_clocks = 6 + tx_shift_clocks;
+        const TX_COMPARE_BIT_COUNT_CLOCKS: u32 = 3;
 
-        let tx_function_name = format!("uart{0}_send", self.uart_num);
+        // Pin is set 3T before the end of the compare; Sample is taken by the 2nd instruction
+        let tx_bit_wait = phase_step(wait_with_echo_sample(
+            bit_clocks
+                - tx_bit_compare_and_set_pin_clocks
+                - TX_COMPARE_BIT_COUNT_CLOCKS
+                - phase_step_clocks,
+            (bit_clocks / 2).saturating_sub(4 + phase_step_clocks),
+        ));
+
+        // Parity bit is placed into carry in place of the data shift
+        let mut tx_parity_carry_instructions = vec![match self.parity {
+            Parity::Mark => "set1 f, c ; 1T".to_string(),
+            Parity::Space => "set0 f, c ; 1T".to_string(),
+            _ => format!(
+                "sr __gen_{}_parity ; 1T, carry flag will contain parity bit",
+                self.symbol_name("send")
+            ),
+        }];
+        tx_parity_carry_instructions.extend(delay(tx_shift_clocks - 1).into_instructions());
+
+        // Bit counter check is not executed after the parity bit
+        let tx_stop_bit_lag = delay(if parity {
+            tx_bit_set_loop_lag_clocks + TX_COMPARE_BIT_COUNT_CLOCKS
+        } else {
+            tx_bit_set_loop_lag_clocks
+        });
+
+        // Function return and the call of the next send take at least 4T
+        const TX_RETURN_AND_NEXT_CALL_CLOCKS: u32 = 4;
+
+        let tx_report_collision_clocks = if self.echo_check { 4 } else { 0 };
+
+        let tx_stop_bit_wait = wait_with_echo_sample(
+            (self.clocks_per_stop_bit + self.idle_clocks).saturating_sub(
+                TX_SET_PIN_CLOCKS + TX_RETURN_AND_NEXT_CALL_CLOCKS + tx_report_collision_clocks
+            ),
+            (self.clocks_per_stop_bit / 2).saturating_sub(2),
+        );
+
+        let tx_function_name = self.symbol_name("send");
 
         const RX_CHECK_START_BIT_CLOCKS: u32 = 2;
-        const RX_FUNCTION_PRELUDE: u32 = 1;
-        const RX_SET_START_BIT_WAIT_LOOP_COUNTER_CLOCKS: u32 = 1;
-        const RX_VALIDATE_START_BIT_CLOCKS: u32 = 2;
-        const RX_SET_BIT_COUNTER_CLOCKS: u32 = 2;
-        const RX_BIT_LOOP_LAG_CLOCKS: u32 = 6;
+        let rx_function_prelude = if parity { 2 } else { 1 };
 
-        const RX_SET_BIT_WAIT_LOOP_COUNTER_CLOCKS: u32 = 1;
         const RX_SHIFT_CARRY_CLOCKS: u32 = 1;
         const RX_DEC_BIT_COUNTER_CLOCKS: u32 = 1;
         const RX_CHECK_BIT_CLOCKS: u32 = 3;
         const RX_CHECK_BIT_COUNTER_CLOCKS: u32 = 3;
 
-        let rx_start_bit_wait_clocks = self.clocks_per_half_bit
+        // ISR enters after the early check, the polling path takes the same time in the padding
+        // before the entry
+        let rx_isr_lead = Delay::nops(if self.rx_interrupt { ISR_LATENCY_CLOCKS } else { 0 });
+
+        // Samples are placed relative to the start bit sample, so the accumulator starts from the half
+        let rx_start_bit_wait = phase_init(128, delay(self.clocks_per_half_bit
             - RX_CHECK_START_BIT_CLOCKS
-            - RX_FUNCTION_PRELUDE
-            - RX_SET_START_BIT_WAIT_LOOP_COUNTER_CLOCKS
-            - RX_VALIDATE_START_BIT_CLOCKS
-            - RX_SET_BIT_COUNTER_CLOCKS
-            + WAIT_LOOP_MISSING_LOCKS
-            + RX_BIT_LOOP_LAG_CLOCKS;
-        let rx_start_bit_wait_cycles = rx_start_bit_wait_clocks / 4;
-        let rx_start_bit_tail_wait_cycles = rx_start_bit_wait_clocks % 4;
-        let rx_start_bit_tail_wait_instructions =
-            generate_space_optimal_nop_chain(rx_start_bit_tail_wait_cycles);
-
-        let rx_bit_wait_clocks = self.clocks_per_bit
+            - rx_function_prelude
+            - rx_isr_lead.clocks()
+            - phase_init_clocks));
+
+        let rx_count_parity_clocks = if parity_counted { 2 } else { 0 };
+
+        let rx_bit_wait = phase_step(delay(bit_clocks
+            - rx_count_parity_clocks
             - RX_SHIFT_CARRY_CLOCKS
-            - RX_SET_BIT_WAIT_LOOP_COUNTER_CLOCKS
             - RX_DEC_BIT_COUNTER_CLOCKS
             - RX_CHECK_BIT_CLOCKS
             - RX_CHECK_BIT_COUNTER_CLOCKS
-            + WAIT_LOOP_MISSING_LOCKS;
-        let rx_bit_wait_cycles = rx_bit_wait_clocks / 4;
-        let rx_bit_tail_wait_cycles = rx_bit_wait_clocks % 4;
-        let rx_bit_tail_wait_instructions =
-            generate_space_optimal_nop_chain(rx_bit_tail_wait_cycles);
+            - phase_step_clocks));
 
-        let rx_function_name = format!("uart{0}_receive", self.uart_num);
-        let rx_byte_name = format!("uart{0}_rx_byte", self.uart_num);
-        let init_function_name = format!("uart{0}_init", self.uart_num);
-        let rx_wait_ready_function_name = format!("uart{0}_rx_wait_ready", self.uart_num);
+        // Parity bit section has only parity counting (2T) before the wait; Bit counter is checked
+        // before it only when it directly follows the bit loop
+        const RX_COUNT_PARITY_BIT_CLOCKS: u32 = 2;
+        let rx_parity_previous_check_clocks = if nine_bits { 0 } else { RX_CHECK_BIT_COUNTER_CLOCKS };
 
+        let rx_parity_wait = phase_step(delay(bit_clocks
+            - RX_COUNT_PARITY_BIT_CLOCKS
+            - RX_CHECK_BIT_CLOCKS
+            - rx_parity_previous_check_clocks
+            - phase_step_clocks));
+
+        // With runtime waits the bit waits call the wait of the bit or half bit period set in RAM
+        // instead. It takes the period less the reserve, which covers the code of the longest
+        // section, and the rest of the reserve is padded
+        let stop_clocks = self.clocks_per_stop_bit + self.idle_clocks;
+        let stop_periods = stop_clocks.div_ceil(self.clocks_per_bit);
+        let wait_overhead = |section_clocks: u32, wait: &Delay| section_clocks - wait.clocks();
+        let mut bit_waits = Vec::new();
+        if self.tx.is_some() {
+            bit_waits.push(wait_overhead(self.clocks_per_bit, &tx_start_bit_wait));
+            bit_waits.push(wait_overhead(self.clocks_per_bit, &tx_bit_wait));
+            bit_waits.push(wait_overhead(stop_clocks, &tx_stop_bit_wait));
+        }
+        if self.rx.is_some() {
+            bit_waits.push(wait_overhead(self.clocks_per_half_bit, &rx_start_bit_wait));
+            bit_waits.push(wait_overhead(self.clocks_per_bit, &rx_bit_wait));
+            if parity {
+                bit_waits.push(wait_overhead(self.clocks_per_bit, &rx_parity_wait));
+            }
+        }
+        let runtime_wait_reserve = bit_waits.into_iter().max().unwrap_or_default();
+        let runtime_wait_base = runtime_wait_reserve + RUNTIME_WAIT_OVERHEAD_CLOCKS;
+        let wait_bit_function_name = self.symbol_name("wait_bit");
+        let wait_half_bit_function_name = self.symbol_name("wait_half_bit");
+        // Stop bit takes whole periods, at least the configured stop and idle time
+        let runtime_wait = |section_clocks: u32, periods: u32, half: bool, wait: Delay| -> Delay {
+            if !self.runtime_waits() {
+                return wait;
+            }
+            let overhead = wait_overhead(section_clocks, &wait);
+            let function = if half { &wait_half_bit_function_name } else { &wait_bit_function_name };
+            let calls = (0..periods)
+                .map(|_| format!("call _{} ; {}T less than the period", function, runtime_wait_reserve))
+                .collect();
+            with_code(calls, delay(periods * runtime_wait_reserve - overhead))
+        };
+        let tx_start_bit_wait = runtime_wait(self.clocks_per_bit, 1, false, tx_start_bit_wait);
+        let tx_bit_wait = runtime_wait(self.clocks_per_bit, 1, false, tx_bit_wait);
+        let tx_stop_bit_wait = runtime_wait(stop_clocks, stop_periods, false, tx_stop_bit_wait);
+        let rx_start_bit_wait = runtime_wait(self.clocks_per_half_bit, 1, true, rx_start_bit_wait);
+        let rx_bit_wait = runtime_wait(self.clocks_per_bit, 1, false, rx_bit_wait);
+        let rx_parity_wait = runtime_wait(self.clocks_per_bit, 1, false, rx_parity_wait);
+
+        let mut sections = Vec::new();
+        if self.tx.is_some() {
+            sections.push(("tx_start_bit", &tx_start_bit_wait));
+            sections.push(("tx_bit", &tx_bit_wait));
+            sections.push(("tx_stop_bit_lag", &tx_stop_bit_lag));
+            sections.push(("tx_stop_bit", &tx_stop_bit_wait));
+        }
+        if self.rx.is_some() {
+            sections.push(("rx_start_bit", &rx_start_bit_wait));
+            sections.push(("rx_bit", &rx_bit_wait));
+            if parity {
+                sections.push(("rx_parity_bit", &rx_parity_wait));
+            }
+        }
+        let delay_counter_size = sections.iter()
+            .map(|(_, delay)| delay.counter_bytes())
+            .max()
+            .unwrap_or(0);
+        let delay_reports: Vec<_> = sections.iter()
+            .map(|(section, delay)| DelayReport {
+                section,
+                clocks: delay.clocks(),
+                loops: delay.loops().to_vec(),
+                tail_nops: delay.tail_nops(),
+            })
+            .collect();
+
+        // Data is shifted in from MSB, shorter words should be aligned to LSB
+        let rx_align_instructions = (data_bits..8)
+            .map(|_| format!("sr _{} ; 1T", self.symbol_name("rx_byte")))
+            .collect();
+
+        let rx_function_name = self.symbol_name("receive");
+        // Unused fields are filled with the actual timing
+        let rx_timeout = self.rx_timeout.unwrap_or_else(|| RxTimeout::new(self.clocks_per_bit));
+        let rx_byte_name = self.symbol_name("rx_byte");
+        let init_function_name = self.symbol_name("init");
+        let rx_wait_ready_function_name = self.symbol_name("rx_wait_ready");
+        let timer_isr_function_name = self.symbol_name("timer_isr");
+
+        // Timer ISR sends and receives the whole frame as a 16-bit word, LSB first
+        let frame_bit = |word: &str, bit: u8| if bit < 8 {
+            format!("__gen_{}, #{}", word, bit)
+        } else {
+            format!("__gen_{}+1, #{}", word, bit - 8)
+        };
+        // Buffered words are framed by `write` into a separate variable
+        let (tx_frame_function_name, tx_frame) = match self.tx_buffer_size {
+            Some(_) => (self.symbol_name("write"), format!("{}_frame", self.symbol_name("write"))),
+            None => (tx_function_name.clone(), format!("{}_shift", tx_function_name)),
+        };
+        let tx_frame_name = format!("_gen_{}", tx_frame);
+        let tx_frame_parity_instructions = match self.parity {
+            Parity::None | Parity::Space => Vec::new(),
+            Parity::Mark => vec![format!("set1 {}", frame_bit(&tx_frame, data_bits))],
+            Parity::Even | Parity::Odd => {
+                let counter = format!("__gen_{}_parity", tx_function_name);
+                let mut instructions = vec![
+                    format!("mov a, #{}", (self.parity == Parity::Odd) as u8),
+                    format!("mov {}, a", counter),
+                ];
+                for bit in 0..data_bits {
+                    instructions.push(format!("t0sn {}", frame_bit(&tx_frame, bit)));
+                    instructions.push(format!("inc {}", counter));
+                }
+                instructions.push(format!("t0sn {}, #0", counter));
+                instructions.push(format!("set1 {}", frame_bit(&tx_frame, data_bits)));
+                instructions
+            }
+        };
+        let tx_stop_mask = 0xFFFFu16 << (data_bits as u32 + parity as u32);
+
+        // Samples are shifted in from MSB, the stop bit sample ends up in bit 15
+        let rx_samples = self.frame_bits() + 1;
+        let rx_word = format!("{}_word", rx_function_name);
+        let rx_frame_align_instructions = (0..17 - rx_samples)
+            .flat_map(|_| vec![
+                format!("sr __gen_{}+1", rx_word),
+                format!("src __gen_{}", rx_word),
+            ])
+            .collect();
+        let rx_parity_bits = match self.parity {
+            Parity::None => 0..0,
+            Parity::Even | Parity::Odd => 0..data_bits + 1,
+            Parity::Mark | Parity::Space => data_bits..data_bits + 1,
+        };
+        let rx_frame_parity_instructions = if parity {
+            let counter = format!("__gen_{}_parity", rx_function_name);
+            let mut instructions = vec![format!("clear {}", counter)];
+            for bit in rx_parity_bits {
+                instructions.push(format!("t0sn {}", frame_bit(&rx_word, bit)));
+                instructions.push(format!("inc {}", counter));
+            }
+            instructions
+        } else {
+            Vec::new()
+        };
+
+
+        // Runtime wait loops and the rest of clocks for the period
+        let wait_value = |period: u32| {
+            let clocks = period.saturating_sub(runtime_wait_base)
+                .clamp(RUNTIME_WAIT_LOOP_CLOCKS, RUNTIME_MAX_WAIT_CLOCKS);
+            [(clocks / RUNTIME_WAIT_LOOP_CLOCKS) as u8, (clocks % RUNTIME_WAIT_LOOP_CLOCKS) as u8]
+        };
+        let initial_bit_wait = wait_value(self.clocks_per_bit);
+        let initial_half_bit_wait = wait_value(self.clocks_per_half_bit);
+        let runtime_waits = self.runtime_waits().then(|| {
+            // Half bit wait takes at least one loop
+            let min_clocks_per_bit = 2 * (runtime_wait_base + RUNTIME_WAIT_LOOP_CLOCKS);
+            let max_clocks_per_bit = runtime_wait_base + RUNTIME_MAX_WAIT_CLOCKS;
+            RuntimeWaits {
+                reserve_clocks: runtime_wait_reserve,
+                min_clocks_per_bit,
+                max_clocks_per_bit,
+                min_baud: self.frequency.hz().div_ceil(max_clocks_per_bit),
+                max_baud: self.frequency.hz() / min_clocks_per_bit,
+                initial_bit_wait,
+                initial_half_bit_wait,
+            }
+        });
+        let autobaud = self.autobaud.zip(runtime_waits).map(|(sync, waits)| {
+            // Same limit as the error budget, except the clock tolerance: the period is measured
+            // by the MCU clock itself
+            let sampled_bits = self.frame_bits() as f64 + 0.5;
+            let detection = if self.rx_timeout.is_some() { RX_TIMEOUT_POLL_CLOCKS } else { RX_START_DETECTION_CLOCKS };
+            let budget_min_clocks = 2.0 * (AUTOBAUD_MEASUREMENT_ERROR_CLOCKS * sampled_bits
+                + detection as f64 + 0.5);
+            let min_clocks_per_bit = waits.min_clocks_per_bit.max(budget_min_clocks.ceil() as u32);
+            AutobaudReport {
+                sync,
+                min_clocks_per_bit,
+                max_clocks_per_bit: waits.max_clocks_per_bit,
+                min_baud: self.frequency.hz().div_ceil(waits.max_clocks_per_bit),
+                max_baud: self.frequency.hz() / min_clocks_per_bit,
+            }
+        });
+        let baud_table: Vec<_> = self.baud_table.iter()
+            .map(|entry| BaudTableReport {
+                entry: *entry,
+                bit_wait: wait_value(entry.clocks_per_bit),
+                half_bit_wait: wait_value(entry.clocks_per_half_bit),
+            })
+            .collect();
+        let baud_macro_prefix = self.symbol_name("baud").to_uppercase();
+        let baud_table_context = baud_table.iter()
+            .enumerate()
+            .map(|(index, entry)| BaudTableContext {
+                index,
+                baud: entry.entry.baud,
+                macro_name: format!("{}_{}", baud_macro_prefix, entry.entry.baud),
+                bit_loops: entry.bit_wait[0],
+                bit_rest: entry.bit_wait[1],
+                half_bit_loops: entry.half_bit_wait[0],
+                half_bit_rest: entry.half_bit_wait[1],
+            })
+            .collect();
 
         let context = TemplateContext {
             app_name: env!("CARGO_PKG_NAME"),
@@ -522,33 +2498,456 @@ impl UartGenerator {
             baud: self.baud,
 
             tx_function_name,
-            tx_port: self.tx_port.char(),
-            tx_pin: self.tx_pin.num(),
+            tx_enabled: self.tx.is_some(),
+            tx_port,
+            tx_pin,
             tx_inverted: self.invert_tx,
-            tx_start_bit_wait_cycles,
-            tx_start_bit_tail_wait_instructions,
-            tx_bit_wait_cycles,
-            tx_bit_tail_wait_instructions,
-            tx_stop_bit_wait_cycles,
-            tx_stop_bit_tail_wait_instructions,
+            tx_pin_mask: 1 << tx_pin,
+            tx_mark_instruction,
+            tx_space_instruction,
+            tx_echo_check: self.echo_check,
+            tx_collision_name: self.symbol_name("tx_collision"),
+            tx_nine_bits: nine_bits,
+            tx_data_bits: data_bits,
+            tx_parity: parity,
+            tx_parity_counted: parity_counted,
+            tx_parity_initial_value: (self.parity == Parity::Odd) as u8,
+            tx_parity_carry_instructions,
+            tx_start_bit_wait_instructions: tx_start_bit_wait.into_instructions(),
+            tx_bit_wait_instructions: tx_bit_wait.into_instructions(),
+            tx_stop_bit_lag_instructions: tx_stop_bit_lag.into_instructions(),
+            tx_stop_bit_wait_instructions: tx_stop_bit_wait.into_instructions(),
 
             rx_function_name,
             rx_byte_name,
-            rx_port: self.rx_port.char(),
-            rx_pin: self.rx_pin.num(),
+            rx_enabled: self.rx.is_some(),
+            rx_port: self.rx.map(|(port, _)| port.char()).unwrap_or_default(),
+            rx_pin: self.rx.map(|(_, pin)| pin.num()).unwrap_or_default(),
             rx_inverted: self.invert_rx,
-            rx_start_bit_wait_cycles,
-            rx_start_bit_tail_wait_instructions,
-            rx_bit_wait_cycles,
-            rx_bit_tail_wait_instructions,
+            rx_nine_bits: nine_bits,
+            rx_bit8_name: self.symbol_name("rx_bit8"),
+            rx_loop_bits: data_bits.min(8),
+            rx_parity: parity,
+            rx_parity_counted: parity_counted,
+            rx_parity_expected_odd: matches!(self.parity, Parity::Odd | Parity::Mark),
+            rx_parity_wait_instructions: rx_parity_wait.into_instructions(),
+            rx_start_bit_wait_instructions: rx_start_bit_wait.into_instructions(),
+            rx_bit_wait_instructions: rx_bit_wait.into_instructions(),
+            rx_align_instructions,
+
+            rx_interrupt: self.rx_interrupt,
+            rx_isr_function_name: self.symbol_name("rx_isr"),
+            rx_isr_entry_name: self.symbol_name("receive_isr"),
+            rx_interrupt_bit: self.rx
+                .and_then(|(port, pin)| RX_INTERRUPT_PINS.iter().position(|rx| *rx == (port.char(), pin.num())))
+                .unwrap_or_default() as u8,
+            rx_isr_lead_instructions: rx_isr_lead.into_instructions(),
+            rx_available_function_name: self.symbol_name("available"),
+            rx_read_function_name: self.symbol_name("read"),
+            rx_buffer_name: format!("_gen_{}", self.symbol_name("rx_buffer")),
+            rx_buffer_size: self.rx_buffer_size,
+            rx_buffer_mask: self.rx_buffer_size - 1,
+            rx_overflow_name: self.symbol_name("rx_overflow"),
+            rx_error_name: self.symbol_name("rx_error"),
+
+            rx_timeout: self.rx_timeout.is_some(),
+            rx_timeout_function_name: self.symbol_name("receive_timeout"),
+            rx_timeout_macro_prefix: self.symbol_name("rx_timeout").to_uppercase(),
+            rx_timeout_poll_count: rx_timeout.polls_per_tick - 1,
+            rx_timeout_ticks_per_bit: rx_timeout.ticks_per_bit,
+            rx_timeout_tick_clocks: rx_timeout.tick_clocks,
+            rx_timeout_tick_ns: (rx_timeout.tick_clocks as f64 * 1e9 / self.frequency.hz() as f64)
+                .round() as u32,
+
+            timer_enabled: self.timer.is_some(),
+            timer_name: self.timer.map(|setup| setup.timer.name()).unwrap_or_default(),
+            timer_t16: self.timer.is_some_and(|setup| setup.timer == Timer::T16),
+            timer_isr_function_name: timer_isr_function_name.clone(),
+            timer_period_clocks: self.timer.map(|setup| setup.period_clocks).unwrap_or_default(),
+            timer_ticks_per_bit: self.ticks_per_bit,
+            timer_prescaler: self.timer.map(|setup| setup.prescaler).unwrap_or_default(),
+            timer_scaler: self.timer.map(|setup| setup.scaler).unwrap_or_default(),
+            timer_mode_value: self.timer.map(|setup| setup.t16_mode_register()).unwrap_or_default(),
+            timer_scaler_value: self.timer.map(|setup| setup.tm_scaler_register()).unwrap_or_default(),
+            timer_bound_value: self.timer.map(|setup| setup.tm_bound_register()).unwrap_or_default(),
+            timer_reload_value: self.timer
+                .map(|setup| setup.t16_reload(ISR_LATENCY_CLOCKS))
+                .unwrap_or_default(),
+            tx_frame_bits: self.timer_tx_frame_bits(),
+            tx_buffered: self.tx_buffer_size.is_some(),
+            tx_write_function_name: tx_frame_function_name.clone(),
+            tx_flush_function_name: self.symbol_name("flush"),
+            tx_buffer_name: format!("_gen_{}", self.symbol_name("tx_buffer")),
+            tx_buffer_size: self.tx_buffer_size.unwrap_or_default(),
+            tx_buffer_mask: self.tx_buffer_size.unwrap_or(1) - 1,
+            tx_complete_name: self.symbol_name("tx_complete"),
+            tx_frame_function_name,
+            tx_frame_name,
+            tx_stop_mask_low: tx_stop_mask as u8,
+            tx_stop_mask_high: (tx_stop_mask >> 8) as u8,
+            tx_frame_parity_instructions,
+            rx_samples,
+            rx_frame_align_instructions,
+            rx_frame_parity_instructions,
+            rx_decode_function_name: self.symbol_name("rx_decode"),
+            data_mask: ((1u16 << data_bits.min(8)) - 1) as u8,
+            data_masked: data_bits < 8,
+            rx_buffered: self.rx_interrupt || self.timer.is_some(),
+
+            runtime_waits: self.runtime_waits(),
+            autobaud: self.autobaud.is_some(),
+            autobaud_function_name: self.symbol_name("autobaud"),
+            autobaud_sync: format!("{:#04x}", self.autobaud.map(SyncChar::value).unwrap_or_default()),
+            autobaud_falling_edges: self.autobaud.map(SyncChar::falling_edges).unwrap_or_default(),
+            runtime_wait_bit_function_name: wait_bit_function_name,
+            runtime_wait_half_bit_function_name: wait_half_bit_function_name,
+            runtime_bit_wait_name: format!("_gen_{}", self.symbol_name("bit_wait")),
+            runtime_half_bit_wait_name: format!("_gen_{}", self.symbol_name("half_bit_wait")),
+            runtime_wait_counter_name: format!("_gen_{}", self.symbol_name("wait")),
+            runtime_wait_base,
+            autobaud_min_half_bit: runtime_wait_base + RUNTIME_WAIT_LOOP_CLOCKS,
+            runtime_initial_bit_loops: initial_bit_wait[0],
+            runtime_initial_bit_rest: initial_bit_wait[1],
+            runtime_initial_half_bit_loops: initial_half_bit_wait[0],
+            runtime_initial_half_bit_rest: initial_half_bit_wait[1],
+            baud_table_enabled: !baud_table.is_empty(),
+            baud_table_name: format!("_gen_{}", self.symbol_name("baud_table")),
+            baud_table_size: baud_table.len(),
+            baud_table: baud_table_context,
+            baud_macro_prefix,
+            set_baud_function_name: self.symbol_name("set_baud"),
 
             init_function_name,
             rx_wait_ready_function_name,
+            half_duplex: self.half_duplex,
+
+            delay_counter_name,
+            delay_counter_required: delay_counter_size != 0,
+            delay_counter_size,
+
+            fractional_timing: self.fractional_timing.is_some(),
+            phase_name,
+
+            function_storage: if split { "" } else { "static " },
+            header_name: format!("{}.h", self.file_stem()),
+            include_guard: format!("FREEPDK_GEN_{}_H", self.file_stem().to_uppercase()),
         };
 
+        if split && self.template.is_some() {
+            return Err(Error::CustomTemplateSplit);
+        }
+
         let mut renderer = TinyTemplate::new();
-        renderer.add_template("uart", UART_TEMPLATE)?;
-        let rendered = renderer.render("uart", &context)?;
-        Ok(rendered)
+        renderer.set_default_formatter(&tinytemplate::format_unescaped);
+        renderer.add_template("banner", UART_BANNER_TEMPLATE)?;
+        renderer.add_template("f_cpu_check", UART_F_CPU_CHECK_TEMPLATE)?;
+        renderer.add_template("definitions", UART_DEFINITIONS_TEMPLATE)?;
+        let implementation = if self.timer.is_some() {
+            UART_TIMER_IMPLEMENTATION_TEMPLATE
+        } else {
+            UART_IMPLEMENTATION_TEMPLATE
+        };
+        renderer.add_template("implementation", implementation)?;
+        renderer.add_template("includes", UART_INCLUDES_TEMPLATE)?;
+        let builtin = single_file_template(implementation);
+        renderer.add_template("uart", self.template.as_deref().unwrap_or(&builtin))?;
+        renderer.add_template("header", UART_HEADER_TEMPLATE)?;
+        renderer.add_template("source", UART_SOURCE_TEMPLATE)?;
+        let file_context = FileTemplateContext { uart: &context };
+        let files = if split {
+            GeneratedFiles {
+                header: Some(renderer.render("header", &file_context)?),
+                source: renderer.render("source", &file_context)?,
+            }
+        } else {
+            GeneratedFiles { header: None, source: renderer.render("uart", &context)? }
+        };
+
+        // Assembled program gives exact size of the asm parts, C init function is not included.
+        // Header goes first in place of its `#include`. Custom template could use instructions the
+        // simulator doesn't know, its size is not reported then
+        let source = format!("{}{}", files.header.as_deref().unwrap_or_default(), files.source);
+        let program = match Program::from_c_source(&source) {
+            Ok(program) => Some(program),
+            Err(e) if self.template.is_some() => {
+                warn!("Code size of the custom template is not estimated: {}", e);
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let timer = match (self.timer, &program) {
+            (Some(setup), Some(program)) => Some(TimerReport {
+                setup,
+                ticks_per_bit: self.ticks_per_bit,
+                isr_clocks: ISR_LATENCY_CLOCKS
+                    + program.max_clocks(&timer_isr_function_name)?
+                    + ISR_EXIT_CLOCKS,
+            }),
+            _ => None,
+        };
+        let report = UartReport {
+            frequency: self.frequency.hz(),
+            baud: self.baud,
+            clocks_per_bit: self.clocks_per_bit,
+            clocks_per_half_bit: self.clocks_per_half_bit,
+            clocks_per_stop_bit: self.clocks_per_stop_bit,
+            idle_clocks: self.idle_clocks,
+            deviation_percent: self.clock_deviation * 100f64,
+            error_budget: self.error_budget,
+            code_words: program.as_ref().map(Program::len),
+            ram_bytes: program.as_ref().map(Program::ram_bytes),
+            // Busy loops are not generated for the timer, and the runtime waits are reported apart
+            delays: if timer.is_some() || runtime_waits.is_some() { Vec::new() } else { delay_reports },
+            timer,
+            rx_timeout: self.rx_timeout,
+            rx_turnaround: self.rx_turnaround,
+            runtime_waits,
+            autobaud,
+            baud_table,
+        };
+        Ok((files, report))
     }
-}
\ No newline at end of file
+}
+
+
+/// Wait code of a single timed section
+#[derive(Serialize, Debug)]
+pub struct DelayReport {
+    pub section: &'static str,
+    pub clocks: u32,
+    pub loops: Vec<DelayLoop>,
+    pub tail_nops: u32,
+}
+
+/// Timing error sources of the frame, relative to the bit period
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct ErrorBudget {
+    /// Baud rate error due to the rounding of clocks per bit
+    pub rounding_percent: f64,
+    /// RX sample point offset from the middle of the bit, spread over the frame
+    pub sampling_percent: f64,
+    pub clock_tolerance_percent: f64,
+    pub total_percent: f64,
+    /// Accumulated error which keeps the stop bit sample within the stop bit
+    pub allowed_percent: f64,
+}
+
+/// Timings and resource usage of the generated UART
+#[derive(Serialize, Debug)]
+pub struct UartReport {
+    pub frequency: u32,
+    pub baud: u32,
+    pub clocks_per_bit: u32,
+    pub clocks_per_half_bit: u32,
+    pub clocks_per_stop_bit: u32,
+    pub idle_clocks: u32,
+    pub deviation_percent: f64,
+    pub error_budget: ErrorBudget,
+    /// Estimated code size; SDCC-compiled init function is not counted. Not known for the custom
+    /// template which could not be assembled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_words: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ram_bytes: Option<u16>,
+    pub delays: Vec<DelayReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer: Option<TimerReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_timeout: Option<RxTimeout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_turnaround: Option<RxTurnaround>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_waits: Option<RuntimeWaits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autobaud: Option<AutobaudReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub baud_table: Vec<BaudTableReport>,
+}
+
+/// Bit waits of the baud rate set at runtime, by `uart{N}_autobaud` or `uart{N}_set_baud`
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct RuntimeWaits {
+    /// Bit waits take the period less this, the rest is padded by the sections
+    pub reserve_clocks: u32,
+    /// Bit periods the wait loops could take
+    pub min_clocks_per_bit: u32,
+    pub max_clocks_per_bit: u32,
+    pub min_baud: u32,
+    pub max_baud: u32,
+    /// Wait loops and the rest of clocks for the initial baud rate, set up by `uart{N}_init`
+    pub initial_bit_wait: [u8; 2],
+    pub initial_half_bit_wait: [u8; 2],
+}
+
+/// Bit periods `uart{N}_autobaud` accepts
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct AutobaudReport {
+    pub sync: SyncChar,
+    pub min_clocks_per_bit: u32,
+    pub max_clocks_per_bit: u32,
+    pub min_baud: u32,
+    pub max_baud: u32,
+}
+
+/// Baud rate of the `uart{N}_set_baud` table, validated as a single one
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct BaudTableEntry {
+    pub baud: u32,
+    pub clocks_per_bit: u32,
+    pub clocks_per_half_bit: u32,
+    pub deviation_percent: f64,
+}
+
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct BaudTableReport {
+    #[serde(flatten)]
+    pub entry: BaudTableEntry,
+    /// Wait loops and the rest of clocks stored in the table
+    pub bit_wait: [u8; 2],
+    pub half_bit_wait: [u8; 2],
+}
+
+/// Timer settings and the worst-case ISR duration, which should fit in the interrupt period
+#[derive(Serialize, Debug)]
+pub struct TimerReport {
+    #[serde(flatten)]
+    pub setup: TimerSetup,
+    pub ticks_per_bit: u32,
+    /// Including the interrupt latency and exit
+    pub isr_clocks: u32,
+}
+
+impl PeripheralGenerator for UartGenerator {
+    fn name(&self) -> String {
+        self.file_stem()
+    }
+
+    fn resources(&self) -> Result<Resources, peripheral::Error> {
+        let (_, report) = self.generate_with_report(false)?;
+        Ok(Resources {
+            pins: self.pin_claims(),
+            timers: self.timer.map(|setup| setup.timer.name()).into_iter().collect(),
+            // RAM of the custom template which could not be assembled is not counted
+            ram_bytes: report.ram_bytes.unwrap_or_default(),
+        })
+    }
+
+    fn generate(&self, split: bool) -> Result<GeneratedPeripheral, peripheral::Error> {
+        let (files, report) = self.generate_with_report(split)?;
+        let report = serde_json::to_value(report).expect("UART report is always serializable");
+        Ok(GeneratedPeripheral { files, report })
+    }
+}
+
+impl PeripheralConfig for UartOptions {
+    fn build(
+        &self,
+        mcu: &McuConfig,
+        dir: Option<&Path>,
+    ) -> Result<Box<dyn PeripheralGenerator>, peripheral::Error> {
+        let mut config = UartConfig { frequency: mcu.freq, device: mcu.device, ..self.config.clone() };
+        // Single baud rate is checked against the explicit table by the builder
+        if let Some(rates) = &self.baud {
+            config.baud = rates.0.first().copied();
+            if rates.0.len() > 1 {
+                if !config.baud_table.is_empty() {
+                    return Err(Error::BaudTableDefinedTwice.into());
+                }
+                config.baud_table = rates.0.clone();
+            }
+        }
+        let mut builder = UartGenerator::builder().config(config);
+        if let Some(path) = &self.template {
+            // Checked here, so the project build fails before any file is written
+            if self.output.split {
+                return Err(Error::CustomTemplateSplit.into());
+            }
+            let path = dir.map(|dir| dir.join(path)).unwrap_or_else(|| path.clone());
+            let template = fs::read_to_string(&path)
+                .map_err(|e| peripheral::Error::TemplateRead(path, e))?;
+            builder = builder.template(template);
+        }
+        Ok(Box::new(builder.build()?))
+    }
+
+    fn output_options(&self) -> OutputOptions {
+        self.output.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> UartGeneratorBuilder {
+        UartGenerator::builder()
+            .frequency("8mhz".parse().unwrap())
+            .baud(9600)
+            .tx_port("A".parse().unwrap())
+            .tx_pin("4".parse().unwrap())
+            .rx_port("A".parse().unwrap())
+            .rx_pin("3".parse().unwrap())
+    }
+
+    #[test]
+    fn builtin_template_is_accepted_as_custom_one() {
+        let builtin = builder().build().unwrap().generate().unwrap();
+        let custom = builder().template(builtin_template()).build().unwrap();
+        assert_eq!(custom.generate().unwrap(), builtin);
+    }
+
+    #[test]
+    fn template_without_matching_version_header_is_rejected() {
+        let body = single_file_template(UART_IMPLEMENTATION_TEMPLATE);
+        assert!(matches!(
+            builder().template(body.clone()).build(),
+            Err(Error::TemplateHeaderMissing)
+        ));
+        let template = format!("{}{}\n{}", TEMPLATE_HEADER, TEMPLATE_VERSION + 1, body);
+        assert!(matches!(
+            builder().template(template).build(),
+            Err(Error::TemplateVersionMismatch(version)) if version == TEMPLATE_VERSION + 1
+        ));
+        let template = format!("{}x\n{}", TEMPLATE_HEADER, body);
+        assert!(matches!(builder().template(template).build(), Err(Error::TemplateHeaderMissing)));
+    }
+
+    #[test]
+    fn single_baud_rate_keeps_explicit_table() {
+        let mcu = McuConfig { freq: Some("8mhz".parse().unwrap()), device: None };
+        let options = |baud: &str, baud_table: Vec<u32>| {
+            let config = UartConfig {
+                tx_port: Some("A".parse().unwrap()),
+                tx_pin: Some("4".parse().unwrap()),
+                baud_table,
+                ..UartConfig::default()
+            };
+            let mut options = UartOptions::new(config, OutputOptions::default());
+            options.baud = Some(baud.parse().unwrap());
+            options
+        };
+        assert!(options("9600", vec![9600, 19200]).build(&mcu, None).is_ok());
+        assert!(matches!(
+            options("4800", vec![9600, 19200]).build(&mcu, None),
+            Err(peripheral::Error::Uart(Error::InitialBaudNotInTable(4800)))
+        ));
+        assert!(options("9600,19200", Vec::new()).build(&mcu, None).is_ok());
+        assert!(matches!(
+            options("9600,19200", vec![9600, 19200]).build(&mcu, None),
+            Err(peripheral::Error::Uart(Error::BaudTableDefinedTwice))
+        ));
+    }
+
+    #[test]
+    fn custom_template_size_is_estimated_on_best_effort_basis() {
+        let template = builtin_template().replacen("    ; Function prelude\n", "    wdreset\n", 1);
+        let generator = builder().template(template).build().unwrap();
+        let (files, report) = generator.generate_with_report(false).unwrap();
+        assert!(files.source.contains("wdreset"));
+        assert!(report.code_words.is_none());
+        assert!(report.ram_bytes.is_none());
+
+        let (_, report) = builder().build().unwrap().generate_with_report(false).unwrap();
+        assert!(report.code_words.is_some());
+    }
+}